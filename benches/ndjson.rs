@@ -0,0 +1,143 @@
+//! Measures how fast the stream loop frames and parses a simulation's NDJSON
+//! events, compared to the string buffering it replaced.
+//!
+//! Run with `cargo bench --bench ndjson`, setting `EVENTS` and `CHUNK_SIZE`
+//! to change the size of the run and of the chunks it arrives in.
+
+#[allow(dead_code)]
+#[path = "../src/model.rs"]
+mod model;
+
+#[path = "../src/sim/ndjson.rs"]
+mod ndjson;
+
+use model::EventData;
+use ndjson::LineFramer;
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// The number of distinct events generated, which are repeated up to the
+/// number of events in the run.
+const UNIQUE_EVENTS: usize = 10_000;
+
+fn main() {
+    let events = env_var("EVENTS", 2_000_000);
+    let chunk_size = env_var("CHUNK_SIZE", 64 * 1024);
+
+    let data = generate_events(UNIQUE_EVENTS);
+    let repeats = events.div_ceil(UNIQUE_EVENTS);
+    let total_bytes = data.len() * repeats;
+
+    println!(
+        "{} events, {:.1} MB in {} byte chunks",
+        repeats * UNIQUE_EVENTS,
+        total_bytes as f64 / 1_000_000.0,
+        chunk_size
+    );
+
+    report(
+        "string buffer",
+        repeats * UNIQUE_EVENTS,
+        total_bytes,
+        bench_string_buffer(&data, repeats, chunk_size),
+    );
+    report(
+        "line framer",
+        repeats * UNIQUE_EVENTS,
+        total_bytes,
+        bench_line_framer(&data, repeats, chunk_size),
+    );
+}
+
+/// The stream loop before it parsed events straight from bytes.
+fn bench_string_buffer(data: &[u8], repeats: usize, chunk_size: usize) -> Duration {
+    let started_at = Instant::now();
+    let mut buffer = String::new();
+    let mut parsed = 0;
+
+    for _ in 0..repeats {
+        for chunk in data.chunks(chunk_size) {
+            buffer.push_str(&String::from_utf8_lossy(chunk));
+
+            while let Some(newline_pos) = buffer.find('\n') {
+                let line = buffer[..newline_pos].trim().to_string();
+                buffer = buffer[newline_pos + 1..].to_string();
+
+                if !line.is_empty() && serde_json::from_str::<EventData>(&line).is_ok() {
+                    parsed += 1;
+                }
+            }
+        }
+    }
+
+    black_box(parsed);
+    started_at.elapsed()
+}
+
+fn bench_line_framer(data: &[u8], repeats: usize, chunk_size: usize) -> Duration {
+    let started_at = Instant::now();
+    let mut framer = LineFramer::default();
+    let mut parsed = 0;
+
+    for _ in 0..repeats {
+        for chunk in data.chunks(chunk_size) {
+            framer.push(chunk);
+
+            while let Some(line) = framer.next_line() {
+                if !line.trim_ascii().is_empty()
+                    && serde_json::from_slice::<EventData>(&line).is_ok()
+                {
+                    parsed += 1;
+                }
+            }
+        }
+    }
+
+    black_box(parsed);
+    started_at.elapsed()
+}
+
+fn generate_events(count: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for id in 1..=count {
+        let event = serde_json::json!({
+            "type": "effect",
+            "id": id,
+            "effect": "users.create",
+            "system": "db",
+            "offset": id * 1000,
+            "value": {
+                "id": id,
+                "name": format!("Zoë Ångström {}", id),
+                "email": format!("user{}@example.com", id),
+                "active": id % 2 == 0,
+            },
+        });
+
+        serde_json::to_writer(&mut data, &event).unwrap();
+        data.push(b'\n');
+    }
+
+    data
+}
+
+fn report(name: &str, events: usize, bytes: usize, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+
+    println!(
+        "{:<14} {:>8.2}s {:>12.0} events/s {:>8.1} MB/s",
+        name,
+        seconds,
+        events as f64 / seconds,
+        bytes as f64 / 1_000_000.0 / seconds
+    );
+}
+
+fn env_var(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -19,6 +20,55 @@ pub struct Config {
     pub seed: u64,
     pub start: Option<String>,
     pub end: Option<String>,
+    #[serde(default = "default_record_events")]
+    pub record_events: bool,
+    #[serde(default)]
+    pub runs: RunsConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How long to wait for a connection to the API, such as `10s`.
+    pub connect_timeout: Option<String>,
+    /// How long to wait for the API to respond or to send more of a
+    /// response, such as `60s`, except while streaming a run's events.
+    pub request_timeout: Option<String>,
+    /// How long to wait for more of a run's event stream before reconnecting
+    /// to it, such as `5m`.
+    pub stream_timeout: Option<String>,
+    /// A PEM file of certificates to trust in addition to the built-in roots.
+    pub ca_cert_file: Option<String>,
+    /// The URL of a proxy to send every request through.
+    pub proxy: Option<String>,
+    /// Whether to accept invalid TLS certificates, such as those of a local
+    /// stand-in for the API.
+    #[serde(default)]
+    pub insecure: bool,
+    /// The number of effects or systems fetched per request, which must not
+    /// exceed the API's own limit.
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+}
+
+/// The retention policy applied by `rngo sim runs prune`.
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RunsConfig {
+    /// The number of most recent runs to keep.
+    pub keep: Option<usize>,
+    /// The age after which runs are deleted, such as `7d`.
+    pub older_than: Option<String>,
+}
+
+/// How failed API requests and dropped streams are retried.
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryConfig {
+    /// The most times a request is attempted, including the first.
+    pub max_attempts: Option<u32>,
+    /// The delay before the first retry, such as `500ms`, which doubles with
+    /// each further attempt.
+    pub initial_delay: Option<String>,
+    /// The longest delay between attempts, such as `30s`.
+    pub max_delay: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -52,6 +102,21 @@ fn default_seed() -> u64 {
     1
 }
 
+fn default_record_events() -> bool {
+    true
+}
+
+fn default_page_size() -> usize {
+    10
+}
+
+/// Parses a duration from the config, such as `30s`, naming the key it was
+/// set for if it is invalid.
+pub fn parse_duration(value: &str, key: &str) -> Result<Duration> {
+    humantime::parse_duration(value)
+        .with_context(|| format!("Invalid duration '{}' for {}", value, key))
+}
+
 pub fn get_config() -> Result<Config> {
     let user_config = config::Config::builder()
         .add_source(config::File::from(user_config_file_path()?).required(false))
@@ -14,7 +14,7 @@ pub async fn infer(
     let _ = dotenvy::dotenv();
 
     let config = crate::config::get_config()?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client(&config)?;
 
     let response = client
         .get(format!(
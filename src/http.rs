@@ -0,0 +1,73 @@
+use crate::config::{Config, parse_duration};
+use anyhow::{Context, Result};
+use reqwest::{Certificate, Client, ClientBuilder, Proxy};
+use std::fs;
+use std::time::Duration;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_STREAM_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const USER_AGENT: &str = concat!("rngo-cli/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the HTTP client used for every request, configured with the
+/// timeouts, proxy and certificates from the config.
+///
+/// The request timeout bounds each wait for data rather than the whole
+/// request, so that large responses aren't cut off while they keep arriving.
+pub fn client(config: &Config) -> Result<Client> {
+    let request_timeout = match &config.request_timeout {
+        Some(request_timeout) => parse_duration(request_timeout, "requestTimeout")?,
+        None => DEFAULT_REQUEST_TIMEOUT,
+    };
+
+    builder(config)?
+        .read_timeout(request_timeout)
+        .build()
+        .with_context(|| "Failed to create HTTP client")
+}
+
+/// Builds the HTTP client for streams of events, whose timeout is far more
+/// generous than the request timeout, since a simulation may take a while to
+/// produce its next event. A stream that stays idle past it is reconnected.
+pub fn stream_client(config: &Config) -> Result<Client> {
+    let stream_timeout = match &config.stream_timeout {
+        Some(stream_timeout) => parse_duration(stream_timeout, "streamTimeout")?,
+        None => DEFAULT_STREAM_TIMEOUT,
+    };
+
+    builder(config)?
+        .read_timeout(stream_timeout)
+        .build()
+        .with_context(|| "Failed to create HTTP client")
+}
+
+fn builder(config: &Config) -> Result<ClientBuilder> {
+    let connect_timeout = match &config.connect_timeout {
+        Some(connect_timeout) => parse_duration(connect_timeout, "connectTimeout")?,
+        None => DEFAULT_CONNECT_TIMEOUT,
+    };
+
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(connect_timeout)
+        .danger_accept_invalid_certs(config.insecure);
+
+    if let Some(ca_cert_file) = &config.ca_cert_file {
+        let pem = fs::read(ca_cert_file)
+            .with_context(|| format!("Failed to read CA certificate file {}", ca_cert_file))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA certificate file {}", ca_cert_file))?;
+
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(proxy) = &config.proxy {
+        builder = builder
+            .proxy(Proxy::all(proxy).with_context(|| format!("Invalid proxy URL '{}'", proxy))?);
+    }
+
+    Ok(builder)
+}
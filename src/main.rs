@@ -2,6 +2,7 @@ mod ai;
 mod auth;
 mod config;
 mod effect;
+mod http;
 mod model;
 mod sim;
 mod system;
@@ -9,6 +10,8 @@ mod system;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use config::AiAgent;
+use sim::remote::OutputFormat;
+
 #[derive(Debug, Parser)]
 #[command(name = "rngo")]
 #[command(
@@ -79,6 +82,178 @@ enum SimCommands {
         /// Stream the simulation data to stdout
         #[arg(long)]
         stdout: bool,
+
+        /// Don't show progress while the simulation is streamed
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// Fail the run once it has reported N error events, 1 if no N is given
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        fail_on_error: Option<u64>,
+
+        /// Create the run without streaming it, to attach to later
+        #[arg(long, conflicts_with_all = ["stdout", "quiet", "fail_on_error"])]
+        detach: bool,
+    },
+    /// Stream a run created with --detach into its outputs and systems.
+    Attach {
+        /// The key of the simulation
+        key: String,
+
+        /// The index of the run
+        index: u64,
+
+        /// Don't show progress while the run is streamed
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// Fail the run once it has reported N error events, 1 if no N is given
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        fail_on_error: Option<u64>,
+    },
+    /// Create or update the simulation without running it.
+    Push {
+        /// The sim file to push
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// Show what would be pushed and how it differs from the stored
+        /// simulation, without pushing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List the simulations stored in the API.
+    List {
+        /// How to print the simulations
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Show a simulation stored in the API.
+    Show {
+        /// The key of the simulation
+        key: String,
+
+        /// How to print the simulation
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Delete a simulation and its runs from the API.
+    Delete {
+        /// The key of the simulation
+        key: String,
+
+        /// Delete the simulation without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// How to print the result
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Download the data of an existing run into .rngo/runs.
+    Download {
+        /// The key of the simulation
+        key: String,
+
+        /// The index of the run
+        index: u64,
+
+        /// Don't show progress while the run is streamed
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// How to print the summary
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Write the effects and systems of a simulation to .rngo files.
+    Pull {
+        /// The key of the simulation, defaults to the project's simulation
+        key: Option<String>,
+
+        /// Write the files without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Validate the simulation definitions locally, without calling the API.
+    Validate {
+        /// The sim file to validate
+        #[arg(short, long)]
+        file: Option<String>,
+    },
+    /// Import the recorded events of a previous run into its systems again.
+    Replay {
+        /// The index of the run to replay, or `last`
+        index: String,
+
+        /// A captured event stream to replay instead of the run's recorded
+        /// events, or `-` for stdin
+        #[arg(short, long)]
+        events: Option<String>,
+    },
+    /// Commands for managing local runs under .rngo/runs.
+    Runs {
+        #[command(subcommand)]
+        command: RunsCommands,
+    },
+    /// Resume an interrupted simulation run.
+    ///
+    /// The run continues from its last checkpoint, which is saved every
+    /// second. After Ctrl-C, outputs skip the events they already received.
+    /// After a crash or `kill -9`, the events received since the last
+    /// checkpoint are delivered again, and import commands such as `psql` may
+    /// already have committed them, so they can be imported twice or fail on
+    /// unique constraints.
+    Resume {
+        /// The index of the run to resume, defaults to the last run
+        index: Option<String>,
+
+        /// Don't show progress while the simulation is streamed
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// Fail the run once it has reported N error events, 1 if no N is given
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        fail_on_error: Option<u64>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum RunsCommands {
+    /// List local runs, or the runs of a simulation stored in the API.
+    List {
+        /// List the runs of this simulation in the API instead
+        #[arg(long, value_name = "KEY")]
+        remote: Option<String>,
+
+        /// How to print the runs
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Show the details of a local run.
+    Show {
+        /// The index of the run, or `last`
+        index: String,
+    },
+    /// Delete local runs.
+    Rm {
+        /// The indexes of the runs to delete
+        #[arg(required = true)]
+        indexes: Vec<String>,
+    },
+    /// Delete local runs according to a retention policy.
+    Prune {
+        /// Keep this many of the most recent runs, defaults to runs.keep in the config
+        #[arg(long)]
+        keep: Option<usize>,
+
+        /// Delete runs older than this, such as 7d, defaults to runs.olderThan in the config
+        #[arg(long)]
+        older_than: Option<String>,
+
+        /// Show the runs that would be deleted without deleting them
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
@@ -113,7 +288,47 @@ async fn main() -> Result<()> {
         },
         Commands::Sim { command } => match command {
             SimCommands::Init {} => sim::init().await,
-            SimCommands::Run { file, stdout } => sim::run(file, stdout).await,
+            SimCommands::Run {
+                file,
+                stdout,
+                quiet,
+                fail_on_error,
+                detach,
+            } => sim::run(file, stdout, quiet, fail_on_error, detach).await,
+            SimCommands::Attach {
+                key,
+                index,
+                quiet,
+                fail_on_error,
+            } => sim::attach(key, index, quiet, fail_on_error).await,
+            SimCommands::Push { file, dry_run } => sim::push(file, dry_run).await,
+            SimCommands::List { output } => sim::remote::list(output).await,
+            SimCommands::Show { key, output } => sim::remote::show(key, output).await,
+            SimCommands::Delete { key, yes, output } => sim::remote::delete(key, yes, output).await,
+            SimCommands::Download {
+                key,
+                index,
+                quiet,
+                output,
+            } => sim::download(key, index, quiet, output).await,
+            SimCommands::Pull { key, yes } => sim::pull(key, yes).await,
+            SimCommands::Validate { file } => sim::validate(file).await,
+            SimCommands::Replay { index, events } => sim::replay(index, events).await,
+            SimCommands::Runs { command } => match command {
+                RunsCommands::List { remote, output } => sim::runs::list(remote, output).await,
+                RunsCommands::Show { index } => sim::runs::show(index).await,
+                RunsCommands::Rm { indexes } => sim::runs::rm(indexes).await,
+                RunsCommands::Prune {
+                    keep,
+                    older_than,
+                    dry_run,
+                } => sim::runs::prune(keep, older_than, dry_run).await,
+            },
+            SimCommands::Resume {
+                index,
+                quiet,
+                fail_on_error,
+            } => sim::resume(index, quiet, fail_on_error).await,
         },
     }
 }
@@ -6,18 +6,76 @@ use serde_json::Value;
 pub enum FormatType {
     Sql,
     Json,
+    Csv,
+    Parquet,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Format {
     #[serde(rename = "type")]
     pub otype: FormatType,
+    /// The field delimiter for CSV, defaults to a comma.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    /// How null values are rendered in CSV, defaults to an empty field.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub null: Option<String>,
+    /// The table that a built-in database system inserts an effect's values
+    /// into, defaults to the effect's entity or key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SystemImport {
     pub before: Option<String>,
-    pub command: String,
+    /// A command that is piped the system's events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// A SQLite database that the system's events are inserted into directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sqlite: Option<SqliteImport>,
+    /// A Postgres database that the system's events are copied into directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postgres: Option<PostgresImport>,
+    /// A MySQL or MariaDB database that the system's events are inserted
+    /// into directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mysql: Option<MysqlImport>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SqliteImport {
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresImport {
+    /// A connection string, such as `postgres://postgres@localhost/app`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The environment variable holding the connection string when `url`
+    /// isn't set, `DATABASE_URL` by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_env: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MysqlImport {
+    /// A connection string, such as `mysql://root@localhost:3306/app`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The environment variable holding the connection string when `url`
+    /// isn't set, `DATABASE_URL` by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_env: Option<String>,
+    /// Whether foreign key checks are disabled while loading, so that tables
+    /// can be loaded in any order. The foreign keys are verified once the
+    /// load is done instead.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disable_foreign_key_checks: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -52,6 +110,8 @@ pub struct Effect {
     pub system: Option<String>,
     pub entity: Option<String>,
     pub format: Option<Format>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
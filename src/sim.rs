@@ -1,9 +1,32 @@
 mod api;
+mod diff;
+mod download;
 mod init;
 pub mod load;
+mod ndjson;
 mod problem;
+mod progress;
+mod pull;
+mod push;
+pub mod remote;
+mod replay;
+mod resume;
+mod retry;
 mod run;
+pub mod runs;
 mod sink;
+mod source_map;
+mod spec;
+mod state;
+mod stats;
+mod stream;
+mod validate;
 
+pub use download::{attach, download};
 pub use init::init;
+pub use pull::pull;
+pub use push::push;
+pub use replay::replay;
+pub use resume::resume;
 pub use run::run;
+pub use validate::validate;
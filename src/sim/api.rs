@@ -1,20 +1,117 @@
+use crate::config::Config;
 use crate::model::{Effect, SimulationRunData, System};
-use anyhow::Result;
-use reqwest::Client;
+use crate::sim::problem::Problem;
+use crate::sim::retry::{self, RetryPolicy};
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Sends authenticated requests to the API, retrying them according to the
+/// configured retry policy.
+pub struct ApiClient {
+    client: Client,
+    stream_client: Client,
+    api_url: String,
+    api_key: String,
+    pub retry: RetryPolicy,
+    /// The number of items requested per page of a list.
+    page_size: usize,
+}
+
+impl ApiClient {
+    pub fn new(config: &Config) -> Result<Self> {
+        let api_key = config
+            .api_key
+            .clone()
+            .ok_or_else(|| anyhow!("Could not find API key"))?;
+
+        Ok(ApiClient {
+            client: crate::http::client(config)?,
+            stream_client: crate::http::stream_client(config)?,
+            api_url: config.api_url.clone(),
+            api_key,
+            retry: RetryPolicy::new(&config.retry)?,
+            page_size: config.page_size.max(1),
+        })
+    }
+
+    /// Builds an authenticated request to a path of the API, such as
+    /// `/simulations`.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_url, path))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+    }
+
+    /// Builds an authenticated request for a stream of events, which waits
+    /// for each event without a timeout.
+    pub fn stream_request(&self, path: &str) -> RequestBuilder {
+        self.stream_client
+            .get(format!("{}{}", self.api_url, path))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+    }
+
+    /// Sends a request, retrying it after connection errors and responses
+    /// such as 429 and 503 until the retry policy's attempts run out. The
+    /// last response is returned whatever its status.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let idempotent = request
+            .try_clone()
+            .and_then(|request| request.build().ok())
+            .is_some_and(|request| request.method().is_idempotent());
+        let mut attempt = 1;
+
+        loop {
+            let result = request
+                .try_clone()
+                .expect("API requests have cloneable bodies")
+                .send()
+                .await;
+
+            let backoff = match &result {
+                Ok(response) => self.retry.response_backoff(response, attempt, idempotent),
+                Err(e) => self.retry.error_backoff(e, attempt, idempotent),
+            };
+
+            let Some(backoff) = backoff.filter(|_| attempt < self.retry.max_attempts) else {
+                return Ok(result?);
+            };
+
+            let reason = match &result {
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+
+            eprintln!(
+                "API request failed ({}), retrying in {} (attempt {} of {})",
+                reason,
+                retry::format_delay(backoff),
+                attempt + 1,
+                self.retry.max_attempts
+            );
+
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
 
 /// Downloads all simulation run data including effects and systems with pagination support.
 ///
-/// This function handles pagination to ensure all effects and systems are downloaded,
-/// regardless of the API's implicit limit.
+/// Effects and systems are fetched concurrently, each a page at a time until
+/// a page comes back empty.
 pub async fn get_simulation_run_data(
-    client: &Client,
-    api_url: &str,
-    api_key: &str,
+    api: &ApiClient,
     simulation_key: &str,
     run_index: u64,
 ) -> Result<SimulationRunData> {
-    let effects = fetch_all_effects(client, api_url, api_key, simulation_key, run_index).await?;
-    let systems = fetch_all_systems(client, api_url, api_key, simulation_key, run_index).await?;
+    let run_path = format!("/simulations/{simulation_key}/runs/{run_index}");
+
+    let (effects, systems) = tokio::try_join!(
+        fetch_all::<Effect>(api, format!("{run_path}/effects")),
+        fetch_all::<System>(api, format!("{run_path}/systems")),
+    )?;
 
     Ok(SimulationRunData {
         index: run_index,
@@ -23,78 +120,163 @@ pub async fn get_simulation_run_data(
     })
 }
 
-/// Fetches all effects with pagination
-async fn fetch_all_effects(
-    client: &Client,
-    api_url: &str,
-    api_key: &str,
+/// Downloads the effects and systems of the simulation stored under a key,
+/// as they were last pushed.
+pub async fn get_simulation_definitions(
+    api: &ApiClient,
     simulation_key: &str,
-    run_index: u64,
-) -> Result<Vec<Effect>> {
-    let mut all_effects = Vec::new();
-    let mut offset = 0;
-    let limit = 10;
+) -> Result<(Vec<Value>, Vec<Value>)> {
+    let simulation_path = format!("/simulations/{simulation_key}");
 
-    loop {
-        let response = client
-            .get(format!(
-                "{api_url}/simulations/{simulation_key}/runs/{run_index}/effects"
-            ))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .query(&[("offset", offset.to_string()), ("limit", limit.to_string())])
-            .send()
-            .await?;
+    tokio::try_join!(
+        fetch_all::<Value>(api, format!("{simulation_path}/effects")),
+        fetch_all::<Value>(api, format!("{simulation_path}/systems")),
+    )
+}
 
-        let effects = response.json::<Vec<Effect>>().await?;
-        let count = effects.len();
+/// Lists every simulation stored in the API.
+pub async fn list_simulations(api: &ApiClient) -> Result<Vec<Value>> {
+    fetch_all(api, "/simulations".to_string()).await
+}
 
-        all_effects.extend(effects);
+/// Lists every run of a simulation.
+pub async fn list_simulation_runs(api: &ApiClient, simulation_key: &str) -> Result<Vec<Value>> {
+    fetch_all(api, format!("/simulations/{simulation_key}/runs")).await
+}
 
-        // If we received fewer items than the limit, we've reached the end
-        if count < limit {
-            break;
-        }
+/// Deletes a simulation along with its runs.
+pub async fn delete_simulation(api: &ApiClient, simulation_key: &str) -> Result<()> {
+    let response = api
+        .send(api.request(Method::DELETE, &format!("/simulations/{simulation_key}")))
+        .await?;
 
-        offset += limit;
+    if !response.status().is_success() {
+        let problem = Problem::from_response(response).await;
+        return Err(problem).with_context(|| "API error while deleting simulation");
     }
 
-    Ok(all_effects)
+    Ok(())
 }
 
-/// Fetches all systems with pagination
-async fn fetch_all_systems(
-    client: &Client,
-    api_url: &str,
-    api_key: &str,
+/// Fetches the simulation stored under a key, or `None` if there isn't one.
+pub async fn get_simulation(api: &ApiClient, simulation_key: &str) -> Result<Option<Value>> {
+    let response = api
+        .send(api.request(Method::GET, &format!("/simulations/{simulation_key}")))
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    if !response.status().is_success() {
+        let problem = Problem::from_response(response).await;
+        return Err(problem).with_context(|| "API error while fetching simulation");
+    }
+
+    Ok(Some(response.json::<Value>().await?))
+}
+
+/// Fetches the simulation stored under a key along with its effects and
+/// systems, keyed as they are in a sim file, or `None` if there isn't one.
+///
+/// The effects and systems are fetched from the same paginated endpoints as
+/// `get_simulation_definitions`, rather than relying on the simulation to
+/// embed them.
+pub async fn get_simulation_with_definitions(
+    api: &ApiClient,
+    simulation_key: &str,
+) -> Result<Option<Value>> {
+    let Some(mut simulation) = get_simulation(api, simulation_key).await? else {
+        return Ok(None);
+    };
+
+    let (effects, systems) = get_simulation_definitions(api, simulation_key).await?;
+
+    if let Value::Object(simulation) = &mut simulation {
+        simulation.insert("effects".into(), by_key(effects)?);
+        simulation.insert("systems".into(), by_key(systems)?);
+    }
+
+    Ok(Some(simulation))
+}
+
+/// Keys a list of effects or systems by their `key` field, leaving out the
+/// fields the API leaves unset.
+fn by_key(definitions: Vec<Value>) -> Result<Value> {
+    let mut keyed = Map::new();
+
+    for definition in definitions {
+        let Value::Object(mut definition) = definition else {
+            bail!("API returned a definition that is not an object");
+        };
+
+        let key = definition
+            .shift_remove("key")
+            .and_then(|key| key.as_str().map(str::to_string))
+            .ok_or_else(|| anyhow!("API returned a definition without a key"))?;
+
+        definition.retain(|_, value| !value.is_null());
+        keyed.insert(key, Value::Object(definition));
+    }
+
+    Ok(Value::Object(keyed))
+}
+
+/// Asks the API to stop generating the events of a run.
+pub async fn cancel_simulation_run(
+    api: &ApiClient,
     simulation_key: &str,
     run_index: u64,
-) -> Result<Vec<System>> {
-    let mut all_systems = Vec::new();
+) -> Result<()> {
+    let response = api
+        .send(api.request(
+            Method::POST,
+            &format!("/simulations/{simulation_key}/runs/{run_index}/cancel"),
+        ))
+        .await?;
+
+    if !response.status().is_success() {
+        let problem = Problem::from_response(response).await;
+        return Err(problem).with_context(|| "API error while cancelling run");
+    }
+
+    Ok(())
+}
+
+/// Fetches every item of a paginated list
+async fn fetch_all<T: DeserializeOwned>(api: &ApiClient, path: String) -> Result<Vec<T>> {
+    let mut all_items = Vec::new();
     let mut offset = 0;
-    let limit = 10;
+    let limit = api.page_size;
 
     loop {
-        let response = client
-            .get(format!(
-                "{api_url}/simulations/{simulation_key}/runs/{run_index}/systems"
-            ))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .query(&[("offset", offset.to_string()), ("limit", limit.to_string())])
-            .send()
+        let response = api
+            .send(
+                api.request(Method::GET, &path)
+                    .query(&[("offset", offset.to_string()), ("limit", limit.to_string())]),
+            )
             .await?;
 
-        let systems = response.json::<Vec<System>>().await?;
-        let count = systems.len();
+        if !response.status().is_success() {
+            let problem = Problem::from_response(response).await;
+            return Err(problem).with_context(|| format!("API error while fetching {}", path));
+        }
 
-        all_systems.extend(systems);
+        let items = response
+            .json::<Vec<T>>()
+            .await
+            .with_context(|| format!("Invalid response while fetching {}", path))?;
+        let count = items.len();
 
-        // If we received fewer items than the limit, we've reached the end
-        if count < limit {
+        // The API may return fewer items than the limit before the end, such
+        // as when it caps the page size, so only an empty page ends the list
+        if count == 0 {
             break;
         }
 
-        offset += limit;
+        all_items.extend(items);
+        offset += count;
     }
 
-    Ok(all_systems)
+    Ok(all_items)
 }
@@ -0,0 +1,94 @@
+use crate::sim::problem::{PathPart, format_path};
+use serde_json::Value;
+use std::fmt;
+
+/// The longest rendering of a value shown in a change.
+const MAX_VALUE_LENGTH: usize = 60;
+
+/// A difference between two JSON values at a path.
+pub enum Change {
+    Added(Vec<PathPart>, Value),
+    Removed(Vec<PathPart>, Value),
+    Changed(Vec<PathPart>, Value, Value),
+}
+
+/// Lists the paths at which `new` differs from `old`, descending into objects
+/// and arrays so that only the values that changed are reported.
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(&mut Vec::new(), old, new, &mut changes);
+    changes
+}
+
+fn diff_at(path: &mut Vec<PathPart>, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                path.push(PathPart::Field(key.clone()));
+                match new.get(key) {
+                    Some(new_value) => diff_at(path, old_value, new_value, changes),
+                    None => changes.push(Change::Removed(path.clone(), old_value.clone())),
+                }
+                path.pop();
+            }
+
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    path.push(PathPart::Field(key.clone()));
+                    changes.push(Change::Added(path.clone(), new_value.clone()));
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                path.push(PathPart::Index(index as i64));
+                match (old.get(index), new.get(index)) {
+                    (Some(old_value), Some(new_value)) => {
+                        diff_at(path, old_value, new_value, changes)
+                    }
+                    (Some(old_value), None) => {
+                        changes.push(Change::Removed(path.clone(), old_value.clone()))
+                    }
+                    (None, Some(new_value)) => {
+                        changes.push(Change::Added(path.clone(), new_value.clone()))
+                    }
+                    (None, None) => {}
+                }
+                path.pop();
+            }
+        }
+        _ if old != new => changes.push(Change::Changed(path.clone(), old.clone(), new.clone())),
+        _ => {}
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(path, value) => {
+                write!(f, "+ {}: {}", format_path(path), summarize(value))
+            }
+            Change::Removed(path, value) => {
+                write!(f, "- {}: {}", format_path(path), summarize(value))
+            }
+            Change::Changed(path, old, new) => write!(
+                f,
+                "~ {}: {} -> {}",
+                format_path(path),
+                summarize(old),
+                summarize(new)
+            ),
+        }
+    }
+}
+
+/// Renders a value as compact JSON, cut short if it's long.
+fn summarize(value: &Value) -> String {
+    let json = value.to_string();
+
+    match json.char_indices().nth(MAX_VALUE_LENGTH) {
+        Some((end, _)) => format!("{}...", &json[..end]),
+        None => json,
+    }
+}
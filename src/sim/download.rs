@@ -0,0 +1,115 @@
+use crate::model::Simulation;
+use crate::sim::api::{self, ApiClient};
+use crate::sim::progress::{self, Progress};
+use crate::sim::remote::{self, OutputFormat};
+use crate::sim::sink::SimulationSink;
+use crate::sim::state::{self, RunState};
+use crate::sim::{spec, stream};
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+/// Streams the events of an existing run into a local run directory, writing
+/// them to the run's outputs and importing them into its systems as `run`
+/// does.
+pub async fn download(key: String, index: u64, quiet: bool, output: OutputFormat) -> Result<()> {
+    stream_existing_run(key, index, quiet, None, output, false).await
+}
+
+/// Streams a run created with `rngo sim run --detach` into its outputs and
+/// systems, as `run` would have, and makes it the last run.
+pub async fn attach(
+    key: String,
+    index: u64,
+    quiet: bool,
+    fail_on_error: Option<u64>,
+) -> Result<()> {
+    stream_existing_run(key, index, quiet, fail_on_error, OutputFormat::Text, true).await
+}
+
+async fn stream_existing_run(
+    key: String,
+    index: u64,
+    quiet: bool,
+    fail_on_error: Option<u64>,
+    output: OutputFormat,
+    attaching: bool,
+) -> Result<()> {
+    let config = crate::config::get_config()?;
+    let api = ApiClient::new(&config)?;
+
+    let simulation_run_directory = format!(".rngo/runs/{}", index);
+    let simulation_run_directory = Path::new(&simulation_run_directory);
+
+    if simulation_run_directory.exists() {
+        bail!(
+            "{} already exists, remove it with `rngo sim runs rm {}` first",
+            simulation_run_directory.display(),
+            index
+        )
+    }
+
+    let sim = api::get_simulation(&api, &key)
+        .await?
+        .ok_or_else(|| anyhow!("Could not find simulation {}", key))?;
+    let simulation = serde_json::from_value::<Simulation>(sim.clone())
+        .with_context(|| format!("Invalid simulation {} returned by the API", key))?;
+
+    let simulation_run_data = api::get_simulation_run_data(&api, &key, index).await?;
+
+    fs::create_dir_all(simulation_run_directory)?;
+
+    if attaching {
+        state::link_last_run(index)?;
+    }
+
+    spec::write_spec(simulation_run_directory, &simulation, &simulation_run_data)?;
+
+    let simulation_sink = SimulationSink::new(simulation_run_data, config.record_events)
+        .await?
+        .with_error_limit(fail_on_error);
+    let mut run_state = RunState::new(key.clone(), index);
+
+    // Progress is hidden when printing JSON, keeping stderr free of redraws
+    let mut progress = Progress::new(
+        quiet || output == OutputFormat::Json,
+        progress::simulated_duration(&sim),
+        0,
+    );
+
+    // A download leaves the run itself alone in the API, while an attached
+    // run belongs to this command as it would to `run`
+    let report = stream::finish_run(
+        &api,
+        &mut run_state,
+        simulation_sink,
+        &mut progress,
+        Some(simulation_run_directory),
+        attaching,
+    )
+    .await?;
+
+    if output == OutputFormat::Json {
+        remote::print_json(&json!({
+            "simulation": key,
+            "index": index,
+            "directory": simulation_run_directory,
+            "stats": report.stats,
+        }))?;
+    } else {
+        if attaching {
+            println!("Attached to simulation run");
+        } else {
+            println!("Downloaded simulation run");
+        }
+        println!("  fs:  {}", simulation_run_directory.display());
+        println!("  run: https://rngo.dev/simulations/{}/runs/{}", key, index);
+        println!("Summary");
+        println!("{}", report.stats);
+    }
+
+    report
+        .stats
+        .ensure_error_limit_not_reached(fail_on_error, Some(simulation_run_directory))
+}
@@ -1,11 +1,16 @@
 use anyhow::{Context, Result, anyhow, bail};
+use serde::de::DeserializeOwned;
 use serde_json::{Map, Value};
 use std::fs;
 use std::path::Path;
 
 use crate::config::Config;
+use crate::sim::source_map::SourceMap;
 
-pub fn load_sim_from_file(sim_path: String) -> Result<Value> {
+/// The type of an effect whose file doesn't set one.
+pub const DEFAULT_EFFECT_TYPE: &str = "state.create";
+
+pub fn load_sim_from_file(sim_path: String) -> Result<(Value, SourceMap)> {
     let path = Path::new(&sim_path);
 
     if !path.exists() {
@@ -13,11 +18,15 @@ pub fn load_sim_from_file(sim_path: String) -> Result<Value> {
     }
 
     let file_content = fs::read_to_string(path)?;
-    serde_yaml::from_str(&file_content)
-        .with_context(|| format!("Failed to parse sim file at {}", path.to_string_lossy()))
+    let sim = parse_yaml(path, &file_content, "sim file")?;
+
+    let mut source_map = SourceMap::default();
+    source_map.add_file(&[], path, &file_content);
+
+    Ok((sim, source_map))
 }
 
-pub fn load_sim_from_project_directory(config: &Config) -> Result<Value> {
+pub fn load_sim_from_project_directory(config: &Config) -> Result<(Value, SourceMap)> {
     let rngo_path = Path::new(".rngo");
     let effects_path = rngo_path.join("effects");
 
@@ -29,22 +38,23 @@ pub fn load_sim_from_project_directory(config: &Config) -> Result<Value> {
     })?;
 
     let mut effects_map = Map::new();
+    let mut source_map = SourceMap::default();
 
     for entry in effect_files {
         let entry = entry?;
         let path = entry.path();
 
         let content = fs::read_to_string(&path)?;
-        let yaml_value: serde_yaml::Value = serde_yaml::from_str(&content).with_context(|| {
-            format!("Failed to parse effect file at {}", path.to_string_lossy())
-        })?;
+        let yaml_value: serde_yaml::Value = parse_yaml(&path, &content, "effect file")?;
         let mut json_value: serde_json::Value = serde_json::to_value(yaml_value)?;
 
         if let Some(obj) = json_value.as_object_mut() {
-            obj.entry("type").or_insert_with(|| "state.create".into());
+            obj.entry("type")
+                .or_insert_with(|| DEFAULT_EFFECT_TYPE.into());
         }
 
         if let Some(filename) = path.file_stem().and_then(|s| s.to_str()) {
+            source_map.add_file(&["effects", filename], &path, &content);
             effects_map.insert(filename.to_string(), json_value);
         }
     }
@@ -53,25 +63,12 @@ pub fn load_sim_from_project_directory(config: &Config) -> Result<Value> {
         bail!("No effects found under {}", effects_path.to_string_lossy())
     }
 
-    let systems_map = load_systems_from_project_directory()?;
+    let systems_map = load_systems(&mut source_map)?;
 
     let mut sim = Map::new();
     sim.insert("seed".into(), config.seed.into());
 
-    if let Some(key) = &config.key {
-        sim.insert("key".into(), key.clone().into());
-    } else {
-        let dir_name = std::env::current_dir()
-            .ok()
-            .and_then(|dir| {
-                dir.file_name()
-                    .and_then(|s| s.to_str())
-                    .map(|s| s.to_string())
-            })
-            .ok_or_else(|| anyhow!("Failed to get current directory"))?;
-
-        sim.insert("key".into(), dir_name.into());
-    }
+    sim.insert("key".into(), project_simulation_key(config)?.into());
 
     if let Some(start) = &config.start {
         sim.insert("start".into(), start.clone().into());
@@ -86,10 +83,31 @@ pub fn load_sim_from_project_directory(config: &Config) -> Result<Value> {
     }
     sim.insert("effects".into(), serde_json::Value::Object(effects_map));
 
-    Ok(serde_json::Value::Object(sim))
+    Ok((serde_json::Value::Object(sim), source_map))
+}
+
+/// The key of the project's simulation, which defaults to the name of the
+/// current directory.
+pub fn project_simulation_key(config: &Config) -> Result<String> {
+    if let Some(key) = &config.key {
+        return Ok(key.clone());
+    }
+
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_string())
+        })
+        .ok_or_else(|| anyhow!("Failed to get current directory"))
 }
 
 pub fn load_systems_from_project_directory() -> Result<Map<String, Value>> {
+    load_systems(&mut SourceMap::default())
+}
+
+fn load_systems(source_map: &mut SourceMap) -> Result<Map<String, Value>> {
     let rngo_path = Path::new(".rngo");
     let systems_path = rngo_path.join("systems");
     let mut systems_map = Map::new();
@@ -107,11 +125,11 @@ pub fn load_systems_from_project_directory() -> Result<Map<String, Value>> {
             let path = system.path();
 
             let content = fs::read_to_string(&path)?;
-            let yaml_value: serde_yaml::Value = serde_yaml::from_str(&content)
-                .with_context(|| format!("Failed to parse file at {}", path.to_string_lossy()))?;
+            let yaml_value: serde_yaml::Value = parse_yaml(&path, &content, "system file")?;
             let json_value: serde_json::Value = serde_json::to_value(yaml_value)?;
 
             if let Some(filename) = path.file_stem().and_then(|s| s.to_str()) {
+                source_map.add_file(&["systems", filename], &path, &content);
                 systems_map.insert(filename.to_string(), json_value);
             }
         }
@@ -119,3 +137,20 @@ pub fn load_systems_from_project_directory() -> Result<Map<String, Value>> {
 
     Ok(systems_map)
 }
+
+/// Parses YAML content, reporting the file, line and column of any syntax error.
+fn parse_yaml<T: DeserializeOwned>(path: &Path, content: &str, description: &str) -> Result<T> {
+    serde_yaml::from_str(content).map_err(|e| {
+        let location = e
+            .location()
+            .map(|location| format!(":{}:{}", location.line(), location.column()))
+            .unwrap_or_default();
+
+        anyhow::Error::new(e).context(format!(
+            "Failed to parse {} at {}{}",
+            description,
+            path.to_string_lossy(),
+            location
+        ))
+    })
+}
@@ -0,0 +1,44 @@
+use bytes::{Bytes, BytesMut};
+
+/// Splits a byte stream into NDJSON lines as chunks arrive.
+///
+/// Chunks are appended to a single buffer that complete lines are split off
+/// the front of, so the remainder is never copied, and lines are only decoded
+/// once complete, so multi-byte characters split across chunks stay intact.
+#[derive(Default)]
+pub struct LineFramer {
+    buffer: BytesMut,
+    /// How much of the buffer is known not to contain a newline.
+    scanned: usize,
+}
+
+impl LineFramer {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Returns the next complete line without its line ending, or `None` once
+    /// only a partial line is left.
+    pub fn next_line(&mut self) -> Option<Bytes> {
+        match self.buffer[self.scanned..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+        {
+            Some(position) => {
+                let mut line = self.buffer.split_to(self.scanned + position + 1);
+                self.scanned = 0;
+
+                line.truncate(line.len() - 1);
+                if line.last() == Some(&b'\r') {
+                    line.truncate(line.len() - 1);
+                }
+
+                Some(line.freeze())
+            }
+            None => {
+                self.scanned = self.buffer.len();
+                None
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use crate::sim::source_map::{SourceExcerpt, SourceMap};
 use serde::Deserialize;
 use std::fmt;
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 #[serde(untagged)]
 pub enum PathPart {
     Index(i64),
@@ -12,46 +13,117 @@ pub enum PathPart {
 pub struct ProblemIssue {
     message: String,
     path: Option<Vec<PathPart>>,
+    #[serde(skip)]
+    source: Option<SourceExcerpt>,
+}
+
+impl ProblemIssue {
+    pub fn new(path: Vec<PathPart>, message: String) -> Self {
+        ProblemIssue {
+            message,
+            path: (!path.is_empty()).then_some(path),
+            source: None,
+        }
+    }
 }
 
 impl fmt::Display for ProblemIssue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let str = match &self.path {
-            Some(path) => {
-                let mut path_str = "".to_string();
-
-                for path_part in path {
-                    match path_part {
-                        PathPart::Index(i) => path_str += &format!("[{}]", i),
-                        PathPart::Field(s) if !path_str.is_empty() => {
-                            path_str += &format!(".{}", s)
-                        }
-                        PathPart::Field(s) => path_str = s.into(),
-                    }
-                }
-
-                &format!("{path}: {message}", path = path_str, message = self.message)
-            }
+            Some(path) => &format!(
+                "{path}: {message}",
+                path = format_path(path),
+                message = self.message
+            ),
             None => &self.message,
         };
 
-        write!(f, "{}", str)
+        write!(f, "{}", str)?;
+
+        if let Some(source) = &self.source {
+            write!(f, "\n{}", source)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders a path such as `effects.users.schema[0]`.
+pub fn format_path(path: &[PathPart]) -> String {
+    let mut path_str = "".to_string();
+
+    for path_part in path {
+        match path_part {
+            PathPart::Index(i) => path_str += &format!("[{}]", i),
+            PathPart::Field(s) if !path_str.is_empty() => path_str += &format!(".{}", s),
+            PathPart::Field(s) => path_str = s.into(),
+        }
     }
+
+    path_str
 }
 
+/// The most characters of an unexpected response body shown in a problem.
+const MAX_BODY_EXCERPT: usize = 200;
+
 #[derive(Debug, Deserialize)]
 pub struct Problem {
     title: String,
     issues: Vec<ProblemIssue>,
 }
 
+impl Problem {
+    pub fn new(title: String, issues: Vec<ProblemIssue>) -> Self {
+        Problem { title, issues }
+    }
+
+    /// Reads the problem from a failed API response, falling back to its
+    /// status and an excerpt of its body when it isn't a problem document,
+    /// such as the HTML error page of a proxy.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+
+        if let Ok(problem) = serde_json::from_str::<Problem>(&body) {
+            return problem;
+        }
+
+        let excerpt = body.trim();
+        let issues = match excerpt.char_indices().nth(MAX_BODY_EXCERPT) {
+            Some((end, _)) => vec![ProblemIssue::new(vec![], format!("{}...", &excerpt[..end]))],
+            None if !excerpt.is_empty() => vec![ProblemIssue::new(vec![], excerpt.to_string())],
+            None => vec![],
+        };
+
+        Problem::new(format!("API responded with {}", status), issues)
+    }
+
+    /// Attaches the file, line and column that each issue's path was loaded
+    /// from, so issues are rendered with an excerpt of the source.
+    pub fn locate(mut self, source_map: &SourceMap) -> Self {
+        for issue in self.issues.iter_mut() {
+            if let Some(path) = &issue.path {
+                issue.source = source_map.locate(path);
+            }
+        }
+
+        self
+    }
+}
+
 impl fmt::Display for Problem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if !self.issues.is_empty() {
             let issues = self
                 .issues
                 .iter()
-                .map(|item| format!("  {}", item))
+                .map(|item| {
+                    item.to_string()
+                        .lines()
+                        .map(|line| format!("  {}", line))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
                 .collect::<Vec<_>>()
                 .join("\n");
 
@@ -0,0 +1,226 @@
+use crate::model::EventData;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the progress display is redrawn on a terminal.
+const RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a progress line is logged when stderr is not a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The most effects listed on a terminal, so the display fits on screen.
+const MAX_EFFECT_LINES: usize = 10;
+
+#[derive(Debug)]
+enum Mode {
+    Hidden,
+    Terminal,
+    Log,
+}
+
+/// Reports the progress of a streaming run on stderr.
+///
+/// On a terminal the display is redrawn in place, otherwise a plain log line
+/// is written periodically. Anything else written to stderr while streaming
+/// must go through `println`, or be preceded by `clear`, so that it isn't
+/// overwritten by the next redraw.
+pub struct Progress {
+    mode: Mode,
+    started_at: Instant,
+    last_render: Option<Instant>,
+    drawn_lines: usize,
+    events: u64,
+    effects: BTreeMap<String, u64>,
+    offset: Option<i64>,
+    duration: Option<Duration>,
+    reconnects: u64,
+}
+
+impl Progress {
+    /// Creates a progress display for a run whose simulated time spans
+    /// `duration`, if known, continuing from `events` already received.
+    pub fn new(quiet: bool, duration: Option<Duration>, events: u64) -> Self {
+        let mode = if quiet {
+            Mode::Hidden
+        } else if io::stderr().is_terminal() {
+            Mode::Terminal
+        } else {
+            Mode::Log
+        };
+
+        Progress {
+            mode,
+            started_at: Instant::now(),
+            last_render: None,
+            drawn_lines: 0,
+            events,
+            effects: BTreeMap::new(),
+            offset: None,
+            duration,
+            reconnects: 0,
+        }
+    }
+
+    pub fn record(&mut self, event_data: &EventData) {
+        self.events += 1;
+
+        match event_data {
+            EventData::Effect { effect, offset, .. } => {
+                *self.effects.entry(effect.clone()).or_default() += 1;
+                self.offset = Some(*offset);
+            }
+            EventData::Error { offset, .. } => {
+                if offset.is_some() {
+                    self.offset = *offset;
+                }
+            }
+        }
+    }
+
+    pub fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    /// Redraws the display, or logs a line, if enough time has passed since
+    /// the last one.
+    pub fn tick(&mut self) {
+        let interval = match self.mode {
+            Mode::Hidden => return,
+            Mode::Terminal => RENDER_INTERVAL,
+            Mode::Log => LOG_INTERVAL,
+        };
+
+        if self
+            .last_render
+            .is_some_and(|last_render| last_render.elapsed() < interval)
+        {
+            return;
+        }
+
+        self.last_render = Some(Instant::now());
+
+        match self.mode {
+            Mode::Terminal => self.draw(),
+            Mode::Log => eprintln!("Progress: {}", self.summary()),
+            Mode::Hidden => {}
+        }
+    }
+
+    /// Writes a message to stderr above the display.
+    pub fn println(&mut self, message: impl std::fmt::Display) {
+        self.clear();
+        eprintln!("{}", message);
+    }
+
+    /// Erases the display from the terminal until the next redraw.
+    pub fn clear(&mut self) {
+        if self.drawn_lines > 0 {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\x1b[{}A\x1b[J", self.drawn_lines);
+            let _ = stderr.flush();
+            self.drawn_lines = 0;
+            self.last_render = None;
+        }
+    }
+
+    /// Removes the display once the stream is complete.
+    pub fn finish(&mut self) {
+        self.clear();
+    }
+
+    fn draw(&mut self) {
+        let mut lines = vec![self.summary()];
+
+        for (effect, count) in self.effects.iter().take(MAX_EFFECT_LINES) {
+            lines.push(format!("  {:<32} {:>9}", effect, count));
+        }
+
+        if self.effects.len() > MAX_EFFECT_LINES {
+            lines.push(format!(
+                "  ... and {} more effects",
+                self.effects.len() - MAX_EFFECT_LINES
+            ));
+        }
+
+        let previous_lines = self.drawn_lines;
+        let mut stderr = io::stderr().lock();
+
+        if previous_lines > 0 {
+            let _ = write!(stderr, "\x1b[{}A", previous_lines);
+        }
+
+        for line in &lines {
+            let _ = writeln!(stderr, "\x1b[2K{}", line);
+        }
+
+        let _ = write!(stderr, "\x1b[J");
+        let _ = stderr.flush();
+
+        self.drawn_lines = lines.len();
+    }
+
+    fn summary(&self) -> String {
+        let elapsed = self.started_at.elapsed();
+        let mut parts = vec![format!(
+            "{} events in {}",
+            self.events,
+            format_duration(elapsed)
+        )];
+
+        // Event offsets are milliseconds of simulated time since the start
+        if let Some(offset) = self.offset {
+            let offset = Duration::from_millis(offset.max(0) as u64);
+
+            match self.duration {
+                Some(duration) if !duration.is_zero() => {
+                    let fraction = (offset.as_secs_f64() / duration.as_secs_f64()).min(1.0);
+                    parts.push(format!(
+                        "{:.0}% ({} of {} simulated)",
+                        fraction * 100.0,
+                        format_duration(offset),
+                        format_duration(duration)
+                    ));
+
+                    if fraction > 0.0 {
+                        let remaining = elapsed.as_secs_f64() * (1.0 - fraction) / fraction;
+                        parts.push(format!(
+                            "ETA {}",
+                            format_duration(Duration::from_secs_f64(remaining))
+                        ));
+                    }
+                }
+                _ => parts.push(format!("{} simulated", format_duration(offset))),
+            }
+        }
+
+        if self.reconnects > 0 {
+            parts.push(format!("{} reconnects", self.reconnects));
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Derives the simulated time span of a simulation from its `start` and `end`,
+/// when both are timestamps such as `2025-01-01` or `2025-01-01T12:00:00Z`.
+pub fn simulated_duration(sim: &Value) -> Option<Duration> {
+    let start = parse_timestamp(sim.get("start")?.as_str()?)?;
+    let end = parse_timestamp(sim.get("end")?.as_str()?)?;
+    end.duration_since(start).ok()
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    // Dates without a time are taken to be at midnight
+    if timestamp.len() == 10 {
+        return humantime::parse_rfc3339_weak(&format!("{}T00:00:00", timestamp)).ok();
+    }
+
+    humantime::parse_rfc3339_weak(timestamp).ok()
+}
+
+/// Formats a duration to the second, such as `1h 2m 3s`.
+fn format_duration(duration: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string()
+}
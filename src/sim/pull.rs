@@ -0,0 +1,208 @@
+use crate::sim::api::{self, ApiClient};
+use crate::sim::diff::{self, Change};
+use crate::sim::load;
+use anyhow::{Context, Result, anyhow, bail};
+use inquire::Confirm;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// A file under `.rngo` that would be written by a pull.
+struct PulledFile {
+    path: PathBuf,
+    value: Value,
+    /// The changes from the file's current content, or `None` if the file is
+    /// new or couldn't be parsed.
+    changes: Option<Vec<Change>>,
+}
+
+/// Writes the effects and systems of a remote simulation to `.rngo/effects`
+/// and `.rngo/systems`, showing how they differ from the local files before
+/// overwriting them.
+pub async fn pull(key: Option<String>, yes: bool) -> Result<()> {
+    let config = crate::config::get_config()?;
+    let api = ApiClient::new(&config)?;
+
+    let key = match key {
+        Some(key) => key,
+        None => load::project_simulation_key(&config)?,
+    };
+
+    let (effects, systems) = api::get_simulation_definitions(&api, &key).await?;
+
+    let rngo_path = Path::new(".rngo");
+    let mut files = Vec::new();
+
+    for effect in effects {
+        files.push(plan_file(&rngo_path.join("effects"), effect, true)?);
+    }
+
+    for system in systems {
+        files.push(plan_file(&rngo_path.join("systems"), system, false)?);
+    }
+
+    files.retain(|file| {
+        file.changes
+            .as_ref()
+            .is_none_or(|changes| !changes.is_empty())
+    });
+
+    if files.is_empty() {
+        println!("Local files are up to date with simulation {}", key);
+        return Ok(());
+    }
+
+    for file in &files {
+        match &file.changes {
+            None => println!("Create {}", file.path.display()),
+            Some(changes) => {
+                println!("Update {}", file.path.display());
+                for change in changes {
+                    println!("  {}", change);
+                }
+            }
+        }
+    }
+
+    if !yes {
+        let confirmed = Confirm::new("Write these files?")
+            .with_default(false)
+            .prompt()?;
+
+        if !confirmed {
+            println!("Pull cancelled");
+            return Ok(());
+        }
+    }
+
+    for file in &files {
+        if let Some(parent) = file.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let yaml = serde_yaml::to_string(&file.value)
+            .with_context(|| format!("Failed to serialize {}", file.path.display()))?;
+
+        if fs::read_to_string(&file.path).is_ok_and(|content| content == yaml) {
+            continue;
+        }
+
+        fs::write(&file.path, yaml)
+            .with_context(|| format!("Failed to write {}", file.path.display()))?;
+    }
+
+    println!("Pulled simulation {}", key);
+
+    Ok(())
+}
+
+/// Works out what the file for a remote effect or system would contain,
+/// keeping the key order of the existing file and leaving out the fields
+/// that the project loader fills in, so that unchanged files aren't rewritten.
+fn plan_file(directory: &Path, remote: Value, is_effect: bool) -> Result<PulledFile> {
+    let Value::Object(mut remote) = remote else {
+        return Err(anyhow!("API returned a definition that is not an object"));
+    };
+
+    let key = remote
+        .shift_remove("key")
+        .and_then(|key| key.as_str().map(str::to_string))
+        .ok_or_else(|| anyhow!("API returned a definition without a key"))?;
+
+    // The key names the file, so it must not reach outside the directory
+    if !is_file_name(&key) {
+        bail!(
+            "API returned a definition with key '{}', which is not a valid file name",
+            key
+        );
+    }
+
+    // Fields the API leaves unset are omitted from the file
+    remote.retain(|_, value| !value.is_null());
+
+    // The project loader reads files with any extension, so an existing
+    // `.yaml` file is updated rather than joined by a `.yml` one
+    let path = ["yml", "yaml"]
+        .iter()
+        .map(|extension| directory.join(format!("{}.{}", key, extension)))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| directory.join(format!("{}.yml", key)));
+
+    let existing = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_yaml::from_str::<Value>(&content).ok());
+
+    let mut local = existing.clone();
+
+    if is_effect {
+        let has_type = existing
+            .as_ref()
+            .is_some_and(|value| value.get("type").is_some());
+
+        if !has_type
+            && remote.get("type").and_then(Value::as_str) == Some(load::DEFAULT_EFFECT_TYPE)
+        {
+            remote.shift_remove("type");
+        }
+
+        if let Some(Value::Object(local)) = &mut local
+            && remote.contains_key("type")
+        {
+            local
+                .entry("type")
+                .or_insert_with(|| load::DEFAULT_EFFECT_TYPE.into());
+        }
+    }
+
+    let value = match &existing {
+        Some(existing) => order_like(Value::Object(remote), existing),
+        None => Value::Object(remote),
+    };
+
+    Ok(PulledFile {
+        changes: local.map(|local| diff::diff(&local, &value)),
+        path,
+        value,
+    })
+}
+
+/// Whether a key is a single plain file name, such as `users.create`, rather
+/// than a path like `../config`.
+fn is_file_name(key: &str) -> bool {
+    let mut components = Path::new(key).components();
+
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(name)), None) if name == key
+    ) && !key.contains(['/', '\\'])
+}
+
+/// Reorders the keys of objects in `value` to follow those in `template`,
+/// with any new keys after them.
+fn order_like(value: Value, template: &Value) -> Value {
+    match (value, template) {
+        (Value::Object(mut value), Value::Object(template)) => {
+            let mut ordered = Map::new();
+
+            for (key, template_value) in template {
+                if let Some(value) = value.shift_remove(key) {
+                    ordered.insert(key.clone(), order_like(value, template_value));
+                }
+            }
+
+            ordered.extend(value);
+            Value::Object(ordered)
+        }
+        (Value::Array(values), Value::Array(template)) => Value::Array(
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| match template.get(index) {
+                    Some(template_value) => order_like(value, template_value),
+                    None => value,
+                })
+                .collect(),
+        ),
+        (value, _) => value,
+    }
+}
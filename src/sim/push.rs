@@ -0,0 +1,127 @@
+use crate::config::Config;
+use crate::model::Simulation;
+use crate::sim::api::{self, ApiClient};
+use crate::sim::diff;
+use crate::sim::load;
+use crate::sim::problem::Problem;
+use crate::sim::source_map::SourceMap;
+use anyhow::{Context, Result, anyhow, bail};
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+
+/// Creates or updates the simulation in the API without running it.
+pub async fn push(file: Option<String>, dry_run: bool) -> Result<()> {
+    let config = crate::config::get_config()?;
+    let api = ApiClient::new(&config)?;
+
+    let (key, sim, source_map) = load_simulation(&config, file)?;
+
+    if dry_run {
+        println!("Would push simulation {}", key);
+        println!("{}", serde_json::to_string_pretty(&sim)?);
+
+        match api::get_simulation_with_definitions(&api, &key).await? {
+            None => println!("Simulation {} does not exist yet and would be created", key),
+            Some(remote) => {
+                // Every push sets `output: stream`, which isn't part of the
+                // stored definition
+                let mut local = sim.clone();
+                if let Value::Object(local) = &mut local {
+                    local.shift_remove("output");
+                }
+
+                let changes = diff::diff(&definition(remote, &local), &local);
+
+                if changes.is_empty() {
+                    println!("No changes to simulation {}", key);
+                } else {
+                    println!("Changes to simulation {}", key);
+                    for change in changes {
+                        println!("  {}", change);
+                    }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    let simulation = push_simulation(&api, &key, &sim, &source_map).await?;
+
+    println!("Pushed simulation");
+    println!("  sim: https://rngo.dev/simulations/{}", simulation.key);
+
+    Ok(())
+}
+
+/// Loads the simulation from a sim file or the project directory, returning
+/// its key and the body it is pushed with.
+pub fn load_simulation(
+    config: &Config,
+    file: Option<String>,
+) -> Result<(String, Value, SourceMap)> {
+    let (mut sim, source_map) = if let Some(file) = file {
+        load::load_sim_from_file(file)?
+    } else {
+        load::load_sim_from_project_directory(config)?
+    };
+
+    if let Value::Object(ref mut map) = sim {
+        map.insert("output".into(), "stream".into());
+        let key = map
+            .remove("key")
+            .ok_or_else(|| anyhow!("simulation must have a key"))?
+            .as_str()
+            .ok_or_else(|| anyhow!("simulation key must be a string"))?
+            .to_string();
+
+        Ok((key, sim, source_map))
+    } else {
+        bail!("simulation is not an object")
+    }
+}
+
+/// Creates or updates the simulation, locating any issues the API reports in
+/// the files it was loaded from.
+pub async fn push_simulation(
+    api: &ApiClient,
+    key: &str,
+    sim: &Value,
+    source_map: &SourceMap,
+) -> Result<Simulation> {
+    let push_simulation_response = api
+        .send(
+            api.request(Method::PUT, &format!("/simulations/{key}"))
+                .json(sim),
+        )
+        .await?;
+
+    if !push_simulation_response.status().is_success() {
+        let status = push_simulation_response.status();
+        let problem = Problem::from_response(push_simulation_response)
+            .await
+            .locate(source_map);
+
+        return Err(problem).with_context(|| match status {
+            StatusCode::UNPROCESSABLE_ENTITY => "Validation error",
+            _ => "API error",
+        })?;
+    }
+
+    Ok(push_simulation_response.json::<Simulation>().await?)
+}
+
+/// The part of a stored simulation that a push would replace. Top-level
+/// fields the API adds to stored simulations, such as `key` and `parent`,
+/// aren't part of the pushed body and so aren't compared.
+fn definition(mut remote: Value, sim: &Value) -> Value {
+    if let (Value::Object(remote), Value::Object(sim)) = (&mut remote, sim) {
+        remote.retain(|key, _| sim.contains_key(key) || DEFINITION_FIELDS.contains(&key.as_str()));
+    }
+
+    remote
+}
+
+/// The top-level fields of a simulation definition, which are compared even
+/// when they are missing from the pushed body.
+const DEFINITION_FIELDS: &[&str] = &["seed", "start", "end", "effects", "systems"];
@@ -0,0 +1,144 @@
+use crate::sim::api::{self, ApiClient};
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use inquire::Confirm;
+use serde::Serialize;
+use serde_json::Value;
+
+/// How commands that report on simulations and runs print their results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tables and summaries for reading.
+    #[default]
+    Text,
+    /// JSON for scripting.
+    Json,
+}
+
+pub async fn list(output: OutputFormat) -> Result<()> {
+    let api = api_client()?;
+    let simulations = api::list_simulations(&api).await?;
+
+    if output == OutputFormat::Json {
+        return print_json(&simulations);
+    }
+
+    if simulations.is_empty() {
+        println!("No simulations found");
+        return Ok(());
+    }
+
+    println!("{:<32} {:>6} {:<20}", "KEY", "SEED", "CREATED");
+
+    for simulation in &simulations {
+        println!(
+            "{:<32} {:>6} {:<20}",
+            field(simulation, "key"),
+            field(simulation, "seed"),
+            field(simulation, "createdAt")
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn show(key: String, output: OutputFormat) -> Result<()> {
+    let api = api_client()?;
+    let simulation = api::get_simulation_with_definitions(&api, &key)
+        .await?
+        .ok_or_else(|| anyhow!("Could not find simulation {}", key))?;
+
+    if output == OutputFormat::Json {
+        return print_json(&simulation);
+    }
+
+    println!("Simulation {}", key);
+    println!("  seed:    {}", field(&simulation, "seed"));
+    println!("  start:   {}", field(&simulation, "start"));
+    println!("  end:     {}", field(&simulation, "end"));
+    println!("  created: {}", field(&simulation, "createdAt"));
+    println!("  url:     https://rngo.dev/simulations/{}", key);
+
+    for (title, section) in [("Effects", "effects"), ("Systems", "systems")] {
+        if let Some(items) = simulation.get(section).and_then(Value::as_object)
+            && !items.is_empty()
+        {
+            println!("{}", title);
+            for item_key in items.keys() {
+                println!("  - {}", item_key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn delete(key: String, yes: bool, output: OutputFormat) -> Result<()> {
+    let api = api_client()?;
+
+    if !yes {
+        let confirmed = Confirm::new(&format!("Delete simulation {} and all of its runs?", key))
+            .with_default(false)
+            .prompt()?;
+
+        if !confirmed {
+            println!("Delete cancelled");
+            return Ok(());
+        }
+    }
+
+    api::delete_simulation(&api, &key).await?;
+
+    if output == OutputFormat::Json {
+        return print_json(&serde_json::json!({ "key": key, "deleted": true }));
+    }
+
+    println!("Deleted simulation {}", key);
+    Ok(())
+}
+
+/// Lists the runs of a simulation stored in the API.
+pub async fn list_runs(key: String, output: OutputFormat) -> Result<()> {
+    let api = api_client()?;
+    let runs = api::list_simulation_runs(&api, &key).await?;
+
+    if output == OutputFormat::Json {
+        return print_json(&runs);
+    }
+
+    if runs.is_empty() {
+        println!("No runs found for simulation {}", key);
+        return Ok(());
+    }
+
+    println!("{:<6} {:<20}  STATUS", "INDEX", "CREATED");
+
+    for run in &runs {
+        println!(
+            "{:<6} {:<20}  {}",
+            field(run, "index"),
+            field(run, "createdAt"),
+            field(run, "status")
+        );
+    }
+
+    Ok(())
+}
+
+fn api_client() -> Result<ApiClient> {
+    ApiClient::new(&crate::config::get_config()?)
+}
+
+/// Renders a field of an API object for a table, or `-` if it's missing.
+fn field(value: &Value, key: &str) -> String {
+    match value.get(key) {
+        None | Some(Value::Null) => "-".into(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
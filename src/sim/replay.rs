@@ -0,0 +1,138 @@
+use crate::model::EventData;
+use crate::sim::sink::{self, SimulationSink};
+use crate::sim::{spec, state};
+use anyhow::{Context, Result, bail};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::Instant;
+
+/// Imports the recorded events of a previous run into its systems again,
+/// without calling the API.
+pub async fn replay(index: String, events: Option<String>) -> Result<()> {
+    let simulation_run_directory = state::run_directory(&index)?;
+    let run_index = state::run_index(&simulation_run_directory)?;
+    let simulation_run_data = spec::read_spec(&simulation_run_directory, run_index)?;
+
+    let events_readers: Vec<(String, Box<dyn BufRead>)> = match events.as_deref() {
+        Some("-") => vec![("stdin".into(), Box::new(BufReader::new(io::stdin())))],
+        Some(path) => vec![(path.into(), open_events(Path::new(path))?)],
+        None => {
+            let paths = sink::events_files(&simulation_run_directory)?;
+
+            if paths.is_empty() {
+                bail!(
+                    "No recorded events found for run {}, use --events to replay a captured event stream",
+                    run_index
+                );
+            }
+
+            paths
+                .iter()
+                .map(|path| Ok((path.display().to_string(), open_events(path)?)))
+                .collect::<Result<_>>()?
+        }
+    };
+
+    let mut simulation_sink = SimulationSink::replay(simulation_run_data).await?;
+    let started_at = Instant::now();
+
+    // Resumed runs record the events since their last checkpoint again
+    let mut last_event_id: Option<u64> = None;
+    let mut event_count = 0;
+
+    let read_result: Result<()> = async {
+        let session_count = events_readers.len();
+
+        for (session, (source, reader)) in events_readers.into_iter().enumerate() {
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    // An interrupted session ends in an incomplete frame, and
+                    // the next one starts again from its last checkpoint
+                    Err(e)
+                        if e.kind() == io::ErrorKind::UnexpectedEof
+                            && session + 1 < session_count =>
+                    {
+                        eprintln!(
+                            "Warning: {} ends in an incomplete record, continuing with the next session",
+                            source
+                        );
+                        break;
+                    }
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to read events from {}", source));
+                    }
+                };
+                let line = line.trim();
+
+                if line.is_empty() {
+                    continue;
+                }
+
+                match serde_json::from_str::<EventData>(line) {
+                    Ok(event_data) => {
+                        let id = match &event_data {
+                            EventData::Effect { id, .. } => *id,
+                            EventData::Error { id, .. } => *id,
+                        };
+
+                        if last_event_id.is_some_and(|last_event_id| id <= last_event_id) {
+                            continue;
+                        }
+
+                        last_event_id = Some(id);
+
+                        // Errors were already reported when the run was streamed
+                        if let EventData::Error { .. } = event_data {
+                            continue;
+                        }
+
+                        event_count += 1;
+                        simulation_sink.write_event(event_data).await;
+                    }
+                    Err(e) => eprintln!("Failed to parse NDJSON line: {} - Error: {}", line, e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+    .await;
+
+    // The systems are finished either way, so their imports aren't cut off
+    let mut report = simulation_sink.finish().await?;
+    read_result?;
+    sink::ensure_imports_succeeded(&report.import_statuses)?;
+
+    // Events rejected by built-in database systems are only counted in the
+    // stats, since a replay doesn't append to the run's errors file
+    let error_count = report.stats.error_count();
+    if error_count > 0 {
+        report.stats.add_elapsed(started_at.elapsed());
+        eprintln!("Summary");
+        eprintln!("{}", report.stats);
+        bail!(
+            "Replayed {} events from run {}, {} of them were rejected",
+            event_count,
+            run_index,
+            error_count
+        );
+    }
+
+    println!("Replayed {} events from run {}", event_count, run_index);
+    Ok(())
+}
+
+/// Opens an event stream, decompressing it if it is zstd-compressed.
+fn open_events(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open events at {}", path.display()))?;
+
+    if path.extension().is_some_and(|extension| extension == "zst") {
+        Ok(Box::new(BufReader::new(zstd::Decoder::new(file)?)))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
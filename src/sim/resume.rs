@@ -0,0 +1,57 @@
+use crate::sim::api::{self, ApiClient};
+use crate::sim::progress::Progress;
+use crate::sim::sink::SimulationSink;
+use crate::sim::state::{self, RunState};
+use crate::sim::stream;
+use anyhow::Result;
+
+pub async fn resume(index: Option<String>, quiet: bool, fail_on_error: Option<u64>) -> Result<()> {
+    let config = crate::config::get_config()?;
+    let api = ApiClient::new(&config)?;
+
+    let simulation_run_directory = state::run_directory(index.as_deref().unwrap_or("last"))?;
+    let mut run_state = RunState::load(&simulation_run_directory)?;
+
+    if run_state.complete {
+        println!("Run {} is already complete", run_state.index);
+        return Ok(());
+    }
+
+    let simulation_run_data =
+        api::get_simulation_run_data(&api, &run_state.simulation, run_state.index).await?;
+
+    let simulation_sink = SimulationSink::resume(simulation_run_data, config.record_events)
+        .await?
+        .with_error_limit(fail_on_error)
+        .with_delivered(run_state.delivered.clone());
+
+    // The simulated time span isn't kept with the run, so no ETA is shown
+    let mut progress = Progress::new(quiet, None, run_state.event_count);
+
+    let report = stream::finish_run(
+        &api,
+        &mut run_state,
+        simulation_sink,
+        &mut progress,
+        Some(&simulation_run_directory),
+        true,
+    )
+    .await?;
+
+    println!("Resumed and ran simulation");
+    println!("  fs:  .rngo/runs/{}", run_state.index);
+    println!(
+        "  sim: https://rngo.dev/simulations/{}",
+        run_state.simulation
+    );
+    println!(
+        "  run: https://rngo.dev/simulations/{}/runs/{}",
+        run_state.simulation, run_state.index
+    );
+    println!("Summary");
+    println!("{}", report.stats);
+
+    report
+        .stats
+        .ensure_error_limit_not_reached(fail_on_error, Some(&simulation_run_directory))
+}
@@ -0,0 +1,118 @@
+use crate::config::{self, RetryConfig};
+use anyhow::Result;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::time::Duration;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How failed API requests and dropped streams are retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The most times a request is attempted, including the first.
+    pub max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &RetryConfig) -> Result<Self> {
+        let parse_duration = |value: &Option<String>, default: Duration, key: &str| {
+            value
+                .as_deref()
+                .map(|value| config::parse_duration(value, &format!("retry.{}", key)))
+                .transpose()
+                .map(|duration| duration.unwrap_or(default))
+        };
+
+        Ok(RetryPolicy {
+            max_attempts: config.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+            initial_delay: parse_duration(
+                &config.initial_delay,
+                DEFAULT_INITIAL_DELAY,
+                "initialDelay",
+            )?,
+            max_delay: parse_duration(&config.max_delay, DEFAULT_MAX_DELAY, "maxDelay")?,
+        })
+    }
+
+    /// The delay before retrying after the given number of failed attempts,
+    /// doubling with each attempt up to the maximum, with the upper half
+    /// randomized so that clients don't retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        delay / 2 + delay.mul_f64(rand::random::<f64>() / 2.0)
+    }
+
+    /// The delay before retrying a failed response, or `None` if its status
+    /// means retrying won't help. The `Retry-After` header of 429 and 503
+    /// responses is honored when it is given in seconds, up to the maximum
+    /// delay.
+    ///
+    /// Requests that aren't idempotent, such as creating a run, are only
+    /// retried after a 429 or 503, since the others may have been processed.
+    pub fn response_backoff(
+        &self,
+        response: &Response,
+        attempt: u32,
+        idempotent: bool,
+    ) -> Option<Duration> {
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => Some(
+                retry_after(response)
+                    .map(|delay| delay.min(self.max_delay))
+                    .unwrap_or_else(|| self.backoff(attempt)),
+            ),
+            StatusCode::REQUEST_TIMEOUT
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::GATEWAY_TIMEOUT
+                if idempotent =>
+            {
+                Some(self.backoff(attempt))
+            }
+            _ => None,
+        }
+    }
+
+    /// The delay before retrying a request that failed without a response,
+    /// or `None` if it failed for a reason retrying won't fix. Requests that
+    /// aren't idempotent are only retried if they never reached the API.
+    pub fn error_backoff(
+        &self,
+        error: &reqwest::Error,
+        attempt: u32,
+        idempotent: bool,
+    ) -> Option<Duration> {
+        let retryable = if idempotent {
+            error.is_connect() || error.is_timeout() || error.is_request() || error.is_body()
+        } else {
+            error.is_connect()
+        };
+
+        retryable.then(|| self.backoff(attempt))
+    }
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Formats a retry delay to the millisecond, such as `1s 250ms`.
+pub fn format_delay(delay: Duration) -> String {
+    humantime::format_duration(Duration::from_millis(delay.as_millis() as u64)).to_string()
+}
@@ -0,0 +1,366 @@
+use crate::sim::remote::{self, OutputFormat};
+use crate::sim::state::{self, RunState};
+use crate::sim::stats::{RunStats, format_size};
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RUNS_DIRECTORY: &str = ".rngo/runs";
+
+/// A run directory under `.rngo/runs`, along with whatever metadata it has.
+struct LocalRun {
+    index: u64,
+    directory: PathBuf,
+    state: Option<RunState>,
+    spec: Option<Value>,
+    stats: Option<RunStats>,
+    created_at: SystemTime,
+    size: u64,
+}
+
+impl LocalRun {
+    fn load(index: u64, directory: PathBuf) -> Result<Self> {
+        // Runs created by older versions have no state
+        let state = RunState::load(&directory).ok();
+
+        let spec = fs::read_to_string(directory.join("spec.yml"))
+            .ok()
+            .and_then(|content| serde_yaml::from_str::<Value>(&content).ok());
+
+        // Runs stopped before their first checkpoint have no stats
+        let stats = RunStats::load(&directory).ok();
+
+        let created_at = match state.as_ref().and_then(|state| state.created_at) {
+            Some(created_at) => UNIX_EPOCH + Duration::from_secs(created_at),
+            None => fs::metadata(&directory)?.modified()?,
+        };
+
+        let size = directory_size(&directory)?;
+
+        Ok(LocalRun {
+            index,
+            directory,
+            state,
+            spec,
+            stats,
+            created_at,
+            size,
+        })
+    }
+
+    fn simulation(&self) -> &str {
+        self.state
+            .as_ref()
+            .map(|state| state.simulation.as_str())
+            .unwrap_or("-")
+    }
+
+    fn seed(&self) -> String {
+        self.spec
+            .as_ref()
+            .and_then(|spec| spec.get("seed"))
+            .map(|seed| seed.to_string())
+            .unwrap_or_else(|| "-".into())
+    }
+
+    fn effect_keys(&self) -> Vec<&str> {
+        self.spec
+            .as_ref()
+            .and_then(|spec| spec.get("effects"))
+            .and_then(Value::as_object)
+            .map(|effects| effects.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// The number of events received for each effect, if the run has stats.
+    fn effect_events(&self) -> Vec<(&str, Option<u64>)> {
+        self.effect_keys()
+            .into_iter()
+            .map(|effect_key| {
+                let events = self
+                    .stats
+                    .as_ref()
+                    .map(|stats| stats.effects.get(effect_key).copied().unwrap_or_default());
+
+                (effect_key, events)
+            })
+            .collect()
+    }
+
+    fn events(&self) -> String {
+        self.state
+            .as_ref()
+            .map(|state| state.event_count.to_string())
+            .unwrap_or_else(|| "-".into())
+    }
+
+    fn status(&self) -> &str {
+        match &self.state {
+            Some(state) if state.complete => "complete",
+            Some(_) => "incomplete",
+            None => "-",
+        }
+    }
+
+    fn created(&self) -> String {
+        humantime::format_rfc3339_seconds(self.created_at).to_string()
+    }
+}
+
+pub async fn list(remote: Option<String>, output: OutputFormat) -> Result<()> {
+    if let Some(key) = remote {
+        return remote::list_runs(key, output).await;
+    }
+
+    let runs = load_runs()?;
+
+    if output == OutputFormat::Json {
+        let runs = runs
+            .iter()
+            .map(|run| {
+                json!({
+                    "index": run.index,
+                    "created": run.created(),
+                    "simulation": run.state.as_ref().map(|state| &state.simulation),
+                    "seed": run.spec.as_ref().and_then(|spec| spec.get("seed")),
+                    "effects": run
+                        .effect_events()
+                        .into_iter()
+                        .map(|(effect_key, events)| (effect_key.to_string(), json!(events)))
+                        .collect::<serde_json::Map<_, _>>(),
+                    "events": run.state.as_ref().map(|state| state.event_count),
+                    "size": run.size,
+                    "status": run.state.as_ref().map(|_| run.status()),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        return remote::print_json(&runs);
+    }
+
+    if runs.is_empty() {
+        println!("No runs found under {}", RUNS_DIRECTORY);
+        return Ok(());
+    }
+
+    println!(
+        "{:<6} {:<20} {:<20} {:>6} {:>9} {:>9}  {:<10}  EFFECTS",
+        "INDEX", "CREATED", "SIMULATION", "SEED", "EVENTS", "SIZE", "STATUS"
+    );
+
+    for run in runs {
+        let effects = run
+            .effect_events()
+            .into_iter()
+            .map(|(effect_key, events)| format!("{}={}", effect_key, format_events(events)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let effects = if effects.is_empty() {
+            "-".into()
+        } else {
+            effects
+        };
+
+        println!(
+            "{:<6} {:<20} {:<20} {:>6} {:>9} {:>9}  {:<10}  {}",
+            run.index,
+            run.created(),
+            run.simulation(),
+            run.seed(),
+            run.events(),
+            format_size(run.size),
+            run.status(),
+            effects
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn show(index: String) -> Result<()> {
+    let run_index = state::run_index(&state::run_directory(&index)?)?;
+    let run = LocalRun::load(
+        run_index,
+        Path::new(RUNS_DIRECTORY).join(run_index.to_string()),
+    )?;
+
+    println!("Run {}", run.index);
+    println!("  directory:  {}", run.directory.display());
+    println!("  created:    {}", run.created());
+    println!("  simulation: {}", run.simulation());
+    println!("  seed:       {}", run.seed());
+    println!("  status:     {}", run.status());
+    println!("  events:     {}", run.events());
+
+    if let Some(last_event_id) = run.state.as_ref().and_then(|state| state.last_event_id) {
+        println!("  last event: {}", last_event_id);
+    }
+
+    println!("  size:       {}", format_size(run.size));
+
+    let effect_events = run.effect_events();
+    if !effect_events.is_empty() {
+        println!("Effects");
+        for (effect_key, events) in effect_events {
+            println!("  {:<32} {:>9}", effect_key, format_events(events));
+        }
+    }
+
+    let mut files = fs::read_dir(&run.directory)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| {
+                (
+                    entry.file_name().to_string_lossy().to_string(),
+                    metadata.len(),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    if !files.is_empty() {
+        println!("Files");
+        for (name, size) in files {
+            println!("  {:<32} {:>9}", name, format_size(size));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn rm(indexes: Vec<String>) -> Result<()> {
+    for index in indexes {
+        let directory = state::run_directory(&index)?;
+        let run_index = state::run_index(&directory)?;
+        remove_run(run_index)?;
+        println!("Removed run {}", run_index);
+    }
+
+    Ok(())
+}
+
+pub async fn prune(keep: Option<usize>, older_than: Option<String>, dry_run: bool) -> Result<()> {
+    let config = crate::config::get_config()?;
+
+    let keep = keep.or(config.runs.keep);
+    let older_than = older_than
+        .or(config.runs.older_than)
+        .map(|older_than| crate::config::parse_duration(&older_than, "runs.olderThan"))
+        .transpose()?;
+
+    if keep.is_none() && older_than.is_none() {
+        bail!(
+            "No retention policy, use --keep or --older-than, or set runs.keep or runs.olderThan in .rngo/config.yml"
+        )
+    }
+
+    let now = SystemTime::now();
+    let mut runs = load_runs()?;
+
+    // Newest runs first, so that the first `keep` runs are retained
+    runs.reverse();
+
+    let mut removed = 0;
+
+    for (i, run) in runs.iter().enumerate() {
+        let beyond_keep = keep.is_some_and(|keep| i >= keep);
+        let too_old = older_than.is_some_and(|older_than| {
+            now.duration_since(run.created_at)
+                .is_ok_and(|age| age > older_than)
+        });
+
+        if !beyond_keep && !too_old {
+            continue;
+        }
+
+        if dry_run {
+            println!("Would remove run {}", run.index);
+        } else {
+            remove_run(run.index)?;
+            println!("Removed run {}", run.index);
+        }
+
+        removed += 1;
+    }
+
+    if removed == 0 {
+        println!("No runs to prune");
+    }
+
+    Ok(())
+}
+
+/// Loads every numbered run directory, ordered by index.
+fn load_runs() -> Result<Vec<LocalRun>> {
+    let runs_directory = Path::new(RUNS_DIRECTORY);
+
+    if !runs_directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+
+    for entry in fs::read_dir(runs_directory)? {
+        let entry = entry?;
+
+        // Skips the `last` symlink and anything else that isn't a run
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        if let Some(index) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u64>().ok())
+        {
+            match LocalRun::load(index, entry.path()) {
+                Ok(run) => runs.push(run),
+                Err(e) => eprintln!("Warning: skipping run {}: {:#}", index, e),
+            }
+        }
+    }
+
+    runs.sort_by_key(|run| run.index);
+    Ok(runs)
+}
+
+/// Deletes a run directory, along with the `last` symlink if it points to it.
+fn remove_run(index: u64) -> Result<()> {
+    let directory = Path::new(RUNS_DIRECTORY).join(index.to_string());
+    fs::remove_dir_all(&directory)
+        .with_context(|| format!("Failed to remove {}", directory.display()))?;
+
+    let last_symlink = Path::new(RUNS_DIRECTORY).join("last");
+    if fs::read_link(&last_symlink).is_ok_and(|target| target == Path::new(&index.to_string())) {
+        fs::remove_file(&last_symlink)?;
+    }
+
+    Ok(())
+}
+
+fn format_events(events: Option<u64>) -> String {
+    events
+        .map(|events| events.to_string())
+        .unwrap_or_else(|| "-".into())
+}
+
+fn directory_size(directory: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
@@ -1,37 +1,237 @@
 use crate::model::{EventData, FormatType, SimulationRunData};
-use anyhow::{Context, Result};
-use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use std::process::{Command, Stdio};
+use crate::sim::problem::{self, PathPart};
+use crate::sim::stats::RunStats;
+use anyhow::{Context, Result, anyhow, bail};
+use futures::future;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+mod csv;
+mod database;
+mod mysql;
+mod output;
+mod parquet;
+mod postgres;
+mod sqlite;
+
+/// The files in a run directory that the full event stream is recorded to,
+/// as `events.<n>.ndjson.zst` for the nth session of the run. Each session
+/// writes a file of its own, since one that is killed leaves its zstd frame
+/// incomplete.
+const EVENTS_FILE_PREFIX: &str = "events.";
+const EVENTS_FILE_SUFFIX: &str = ".ndjson.zst";
+
+/// How the recorded events are keyed among the outputs of a run.
+const EVENTS_OUTPUT: &str = "events";
+
+/// The file in a run directory that error events are reported to.
+pub const ERRORS_FILE_NAME: &str = "errors.jsonl";
+
+/// The file in a run directory that events with metadata are sampled to.
+const SAMPLES_FILE_NAME: &str = "samples.jsonl";
+
+use self::csv::CsvOutput;
+use self::database::{DatabaseEvent, ImportReport, ImportReportSender, Statement};
+use self::mysql::MysqlOutput;
+use self::output::{FileWriter, Output, Row};
+use self::parquet::ParquetOutput;
+use self::postgres::PostgresOutput;
+use self::sqlite::SqliteOutput;
 
 pub struct SimulationSink {
     effects: HashMap<String, Effect>,
-    system_sinks: HashMap<String, Box<dyn Write>>,
+    system_sinks: HashMap<String, Output>,
+    import_processes: Vec<ImportProcess>,
     stream: bool,
     samples_sink: Option<Box<dyn Write>>,
+    events_sink: Option<zstd::Encoder<'static, File>>,
+    errors_sink: Option<Box<dyn Write>>,
+    /// The number of error events after which the run is aborted.
+    error_limit: Option<u64>,
+    output_files: Vec<PathBuf>,
+    stats: RunStats,
+    /// Where built-in database systems report the events they rejected and
+    /// the rows they committed.
+    import_report_sender: ImportReportSender,
+    import_report_receiver: mpsc::UnboundedReceiver<ImportReport>,
+    /// The last event written to each output in an earlier session of the
+    /// run, which the events re-delivered on resume aren't written to again.
+    delivered: BTreeMap<String, u64>,
+    last_event_id: Option<u64>,
+}
+
+/// How the sinks of a run are opened.
+#[derive(Clone, Copy, PartialEq)]
+enum OpenMode {
+    /// A new run, which initializes systems and records all outputs.
+    Run,
+    /// An interrupted run, which appends to the existing outputs.
+    Resume,
+    /// A recorded run, which is only imported into its systems again.
+    Replay,
+}
+
+/// A running system import command, whose stdin is held in `system_sinks`.
+struct ImportProcess {
+    system_key: String,
+    child: Child,
+}
+
+/// The exit status of a system's import command once the sink is finished.
+#[derive(Debug)]
+pub struct ImportStatus {
+    pub system_key: String,
+    pub status: ExitStatus,
+}
+
+/// An error event as reported in `errors.jsonl`, with its path rendered the
+/// same way as the paths of API problems.
+#[derive(Serialize)]
+struct ErrorRecord<'a> {
+    id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effect: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    message: &'a str,
+}
+
+/// What a sink wrote, once it is finished.
+pub struct SinkReport {
+    pub import_statuses: Vec<ImportStatus>,
+    pub stats: RunStats,
+    /// The last event written to each output, as recorded with
+    /// `state::record_delivered` when a run is interrupted.
+    pub delivered: BTreeMap<String, u64>,
 }
 
 #[derive(Debug)]
 struct Effect {
     system_key: String,
     format_type: FormatType,
+    /// Whether the effect is imported into a system, rather than a file.
+    system: bool,
+    /// The table the effect is inserted into, if its system is a built-in
+    /// database.
+    table: Option<String>,
 }
 
 impl SimulationSink {
+    /// Prints the events of a run to stdout, and its errors to stderr.
     pub fn stream() -> Self {
+        SimulationSink::empty(true)
+    }
+
+    fn empty(stream: bool) -> Self {
+        let (import_report_sender, import_report_receiver) = mpsc::unbounded_channel();
+
         SimulationSink {
             system_sinks: HashMap::new(),
             effects: HashMap::new(),
-            stream: true,
+            import_processes: Vec::new(),
+            stream,
             samples_sink: None,
+            events_sink: None,
+            errors_sink: None,
+            error_limit: None,
+            output_files: Vec::new(),
+            stats: RunStats::default(),
+            import_report_sender,
+            import_report_receiver,
+            delivered: BTreeMap::new(),
+            last_event_id: None,
         }
     }
 
-    pub fn write_event(&mut self, event_data: EventData) {
+    fn open_errors(&mut self, simulation_directory: &Path) -> Result<()> {
+        let path = simulation_directory.join(ERRORS_FILE_NAME);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", ERRORS_FILE_NAME))?;
+
+        self.errors_sink = Some(Box::new(BufWriter::new(file)));
+        self.output_files.push(path);
+        Ok(())
+    }
+
+    /// Sets the number of error events after which `error_limit_reached`
+    /// reports that the run should be aborted.
+    pub fn with_error_limit(mut self, error_limit: Option<u64>) -> Self {
+        self.error_limit = error_limit;
+        self
+    }
+
+    /// Skips writing events to the outputs that an earlier session of the run
+    /// already wrote them to.
+    pub fn with_delivered(mut self, delivered: BTreeMap<String, u64>) -> Self {
+        self.delivered = delivered;
+        self
+    }
+
+    fn was_delivered(&self, output: &str, id: u64) -> bool {
+        self.delivered.get(output).is_some_and(|last| id <= *last)
+    }
+
+    /// The stats of the run so far, which include the rows committed by
+    /// built-in database systems as of the last flush.
+    pub fn stats(&self) -> &RunStats {
+        &self.stats
+    }
+
+    pub fn error_limit_reached(&self) -> bool {
+        self.error_limit
+            .is_some_and(|error_limit| self.stats.error_count() >= error_limit)
+    }
+
+    /// Writes an event to its outputs, waiting while the queue of the output
+    /// it is routed to is full.
+    pub async fn write_event(&mut self, event_data: EventData) {
+        self.record_import_reports();
+
+        let event_id = match &event_data {
+            EventData::Effect { id, effect, .. } => {
+                self.stats.record_effect(effect);
+                *id
+            }
+            EventData::Error {
+                id,
+                effect,
+                message,
+                ..
+            } => {
+                self.stats.record_error(effect.as_deref(), message);
+                *id
+            }
+        };
+        self.last_event_id = Some(event_id);
+
+        let recorded = self.was_delivered(EVENTS_OUTPUT, event_id);
+        let sampled = self.was_delivered(SAMPLES_FILE_NAME, event_id);
+        let reported = self.was_delivered(ERRORS_FILE_NAME, event_id);
+
+        if let Some(ref mut sink) = self.events_sink
+            && !recorded
+            && let Ok(json) = serde_json::to_string(&event_data)
+        {
+            let _ = writeln!(sink, "{}", json);
+        }
+
         match &event_data {
-            EventData::Effect { metadata, .. } if !metadata.is_empty() => {
+            EventData::Effect { metadata, .. } if !metadata.is_empty() && !sampled => {
                 if let Some(ref mut sink) = self.samples_sink
                     && let Ok(json) = serde_json::to_string(&event_data)
                 {
@@ -41,7 +241,41 @@ impl SimulationSink {
             _ => {}
         }
 
-        if let EventData::Error { .. } = event_data {
+        if let EventData::Error {
+            id,
+            effect,
+            system,
+            offset,
+            path,
+            message,
+        } = &event_data
+        {
+            if let Some(ref mut sink) = self.errors_sink
+                && !reported
+            {
+                let record = ErrorRecord {
+                    id: *id,
+                    effect: effect.as_deref(),
+                    system: system.as_deref(),
+                    offset: *offset,
+                    path: path.as_ref().map(|path| {
+                        let path = path
+                            .iter()
+                            .map(|part| match part.parse() {
+                                Ok(i) => PathPart::Index(i),
+                                Err(_) => PathPart::Field(part.clone()),
+                            })
+                            .collect::<Vec<_>>();
+                        problem::format_path(&path)
+                    }),
+                    message,
+                };
+
+                if let Ok(json) = serde_json::to_string(&record) {
+                    let _ = writeln!(sink, "{}", json);
+                }
+            }
+
             if let Ok(str) = serde_json::to_string(&event_data) {
                 eprintln!("Error: {}", str)
             }
@@ -50,46 +284,310 @@ impl SimulationSink {
                 println!("{}", str)
             }
         } else if let EventData::Effect {
-            effect,
+            id,
+            effect: effect_key,
             value,
             format,
             ..
         } = event_data
-            && let Some(effect) = self.effects.get(&effect)
-            && let Some(system_sink) = self.system_sinks.get_mut(&effect.system_key)
+            && let Some(effect) = self.effects.get(&effect_key)
+            && let Some(output) = self.system_sinks.get_mut(&effect.system_key)
         {
-            let value = match effect.format_type {
-                FormatType::Json => &value.expect("value for JSON entities").to_string(),
-                _ => &format.expect("format for non-JSON entities"),
+            // Rows an earlier session already wrote are still counted, since
+            // the stats it saved only go up to its last checkpoint
+            if effect.system {
+                self.stats.record_system_row(&effect.system_key);
+            }
+
+            if self
+                .delivered
+                .get(&effect.system_key)
+                .is_some_and(|last| id <= *last)
+            {
+                return;
+            }
+
+            let row = match (&effect.table, &effect.format_type) {
+                (Some(table), FormatType::Sql) => Row::Event(DatabaseEvent {
+                    id,
+                    effect: effect_key,
+                    table: table.clone(),
+                    statement: Statement::Sql(format.expect("format for SQL entities")),
+                }),
+                (Some(table), _) => Row::Event(DatabaseEvent {
+                    id,
+                    effect: effect_key,
+                    table: table.clone(),
+                    statement: Statement::Values(value.expect("value for JSON entities")),
+                }),
+                (None, FormatType::Json) => {
+                    Row::Line(value.expect("value for JSON entities").to_string())
+                }
+                (None, FormatType::Sql) => Row::Line(format.expect("format for SQL entities")),
+                (None, FormatType::Csv | FormatType::Parquet) => {
+                    Row::Value(value.expect("value for CSV and Parquet entities"))
+                }
+            };
+
+            output.write(id, row).await;
+        }
+    }
+
+    /// Flushes all buffered outputs, so that everything written so far is
+    /// on disk or has been handed to the import commands.
+    pub async fn flush(&mut self) -> Result<()> {
+        if let Some(ref mut sink) = self.samples_sink {
+            sink.flush()
+                .with_context(|| format!("Failed to flush {}", SAMPLES_FILE_NAME))?;
+        }
+
+        if let Some(ref mut sink) = self.events_sink {
+            sink.flush()
+                .with_context(|| "Failed to flush the recorded events")?;
+        }
+
+        for output in self.system_sinks.values_mut() {
+            output.flush().await?;
+        }
+
+        // Rejected events are reported as they are written, so the errors
+        // file is flushed last to include those of the events just flushed
+        self.record_import_reports();
+        if let Some(ref mut sink) = self.errors_sink {
+            sink.flush()
+                .with_context(|| format!("Failed to flush {}", ERRORS_FILE_NAME))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the events that built-in database systems have rejected so
+    /// far to `errors.jsonl`, counting them toward the error limit, and
+    /// counts the rows they have committed.
+    fn record_import_reports(&mut self) {
+        while let Ok(report) = self.import_report_receiver.try_recv() {
+            let import_error = match report {
+                ImportReport::Error(import_error) => import_error,
+                ImportReport::Rows {
+                    system,
+                    table,
+                    count,
+                } => {
+                    self.stats.record_table_rows(&system, &table, count);
+                    continue;
+                }
             };
 
-            let _ = writeln!(system_sink, "{}", value);
+            self.stats
+                .record_import_error(Some(&import_error.effect), &import_error.message);
+
+            if let Some(ref mut sink) = self.errors_sink {
+                let record = ErrorRecord {
+                    id: import_error.id,
+                    effect: Some(&import_error.effect),
+                    system: Some(&import_error.system),
+                    offset: None,
+                    path: None,
+                    message: &import_error.message,
+                };
+
+                if let Ok(json) = serde_json::to_string(&record) {
+                    let _ = writeln!(sink, "{}", json);
+                }
+            }
         }
     }
+
+    /// Flushes all outputs, closes the stdin of each import command and waits
+    /// for it to exit, reporting the exit status of every system import along
+    /// with the stats of everything written.
+    pub async fn finish(self) -> Result<SinkReport> {
+        self.close(None).await
+    }
+
+    /// Finishes the sink like `finish`, but kills import commands that are
+    /// still running after `timeout`, such as when a run is interrupted.
+    pub async fn finish_within(self, timeout: Duration) -> Result<SinkReport> {
+        self.close(Some(Instant::now() + timeout)).await
+    }
+
+    async fn close(mut self, deadline: Option<Instant>) -> Result<SinkReport> {
+        let files = [
+            (EVENTS_OUTPUT, self.events_sink.is_some()),
+            (SAMPLES_FILE_NAME, self.samples_sink.is_some()),
+            (ERRORS_FILE_NAME, self.errors_sink.is_some()),
+        ];
+
+        // Every output is closed and every import command waited on even when
+        // one of them fails, so that no import is cut off by an early return
+        let mut errors = Vec::new();
+
+        // Outputs are flushed as they are closed below, rather than up front,
+        // so that a deadline also bounds writing their queued rows
+        if let Some(sink) = self.events_sink.take()
+            && let Err(e) = sink.finish()
+        {
+            errors.push(anyhow!(e).context("Failed to finish the recorded events"));
+        }
+
+        if let Some(ref mut sink) = self.samples_sink
+            && let Err(e) = sink.flush()
+        {
+            errors.push(anyhow!(e).context(format!("Failed to flush {}", SAMPLES_FILE_NAME)));
+        }
+
+        // Outputs are closed together, so each drains its queue at its own
+        // pace, and closing an import command's output closes its stdin
+        let closed = future::join_all(
+            self.system_sinks
+                .drain()
+                .map(|(key, output)| async move { (key, output.close(deadline).await) }),
+        )
+        .await;
+
+        let mut delivered = BTreeMap::new();
+
+        for (key, result) in closed {
+            match result {
+                Ok(Some(last_event_id)) => {
+                    delivered.insert(key, last_event_id);
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+
+        // The run's own files were all written in full by this point
+        if let Some(last_event_id) = self.last_event_id {
+            for (file_name, open) in files {
+                if open {
+                    delivered.insert(file_name.to_string(), last_event_id);
+                }
+            }
+        }
+
+        self.record_import_reports();
+        if let Some(ref mut sink) = self.errors_sink
+            && let Err(e) = sink.flush()
+        {
+            errors.push(anyhow!(e).context(format!("Failed to flush {}", ERRORS_FILE_NAME)));
+        }
+
+        let mut statuses = Vec::new();
+
+        for mut import_process in self.import_processes {
+            match wait_until(&mut import_process, deadline).await {
+                Ok(status) => statuses.push(ImportStatus {
+                    system_key: import_process.system_key,
+                    status,
+                }),
+                Err(e) => errors.push(anyhow!(e).context(format!(
+                    "Failed to wait for import command for system {}",
+                    import_process.system_key
+                ))),
+            }
+        }
+
+        match errors.len() {
+            0 => {}
+            1 => return Err(errors.remove(0)),
+            count => bail!(
+                "Failed to close the run's outputs with {} errors\n{}",
+                count,
+                errors
+                    .iter()
+                    .map(|e| format!("  {:#}", e))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        }
+
+        for path in &self.output_files {
+            if let (Some(file_name), Ok(metadata)) = (path.file_name(), fs::metadata(path)) {
+                self.stats
+                    .files
+                    .insert(file_name.to_string_lossy().to_string(), metadata.len());
+            }
+        }
+
+        Ok(SinkReport {
+            import_statuses: statuses,
+            stats: self.stats,
+            delivered,
+        })
+    }
 }
 
-impl TryFrom<SimulationRunData> for SimulationSink {
-    type Error = anyhow::Error;
+impl SimulationSink {
+    /// Initializes the systems of a new run and opens all of its outputs,
+    /// optionally recording the full event stream to the run directory.
+    pub async fn new(simulation_run_data: SimulationRunData, record_events: bool) -> Result<Self> {
+        SimulationSink::open(simulation_run_data, OpenMode::Run, record_events).await
+    }
+
+    /// Reopens the sinks of an interrupted run, appending to its outputs
+    /// without running the systems' `before` commands again.
+    pub async fn resume(
+        simulation_run_data: SimulationRunData,
+        record_events: bool,
+    ) -> Result<Self> {
+        SimulationSink::open(simulation_run_data, OpenMode::Resume, record_events).await
+    }
+
+    /// Initializes the systems of a recorded run and opens their import
+    /// commands, leaving the run's file outputs untouched.
+    pub async fn replay(simulation_run_data: SimulationRunData) -> Result<Self> {
+        SimulationSink::open(simulation_run_data, OpenMode::Replay, false).await
+    }
 
-    fn try_from(simulation_run_data: SimulationRunData) -> Result<Self> {
+    async fn open(
+        simulation_run_data: SimulationRunData,
+        mode: OpenMode,
+        record_events: bool,
+    ) -> Result<Self> {
         // Load .env files before executing any commands
         let _ = dotenvy::dotenv();
 
         let simulation_directory = format!(".rngo/runs/{}", simulation_run_data.index);
         let simulation_directory = Path::new(&simulation_directory);
 
-        let mut simulation_sink = SimulationSink {
-            system_sinks: HashMap::new(),
-            effects: HashMap::new(),
-            stream: false,
-            samples_sink: Some(Box::new(BufWriter::new(
+        let mut simulation_sink = SimulationSink::empty(false);
+
+        // The stats of a resumed run carry on from its earlier sessions, so
+        // that the error limit applies to the run as a whole. A run that was
+        // killed before saving any has none.
+        if mode == OpenMode::Resume {
+            simulation_sink.stats = RunStats::load(simulation_directory).unwrap_or_default();
+        }
+
+        if mode != OpenMode::Replay {
+            simulation_sink.open_errors(simulation_directory)?;
+
+            simulation_sink.samples_sink = Some(Box::new(BufWriter::new(
                 OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(simulation_directory.join("samples.jsonl"))
+                    .open(simulation_directory.join(SAMPLES_FILE_NAME))
                     .expect("Failed to open samples.jsonl"),
-            ))),
-        };
+            )));
+
+            simulation_sink
+                .output_files
+                .push(simulation_directory.join(SAMPLES_FILE_NAME));
+        }
+
+        if record_events {
+            let session = events_files(simulation_directory)?.len();
+            let path = simulation_directory.join(format!(
+                "{}{}{}",
+                EVENTS_FILE_PREFIX, session, EVENTS_FILE_SUFFIX
+            ));
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+
+            simulation_sink.events_sink = Some(zstd::Encoder::new(file, 0)?);
+            simulation_sink.output_files.push(path);
+        }
 
         // Track which systems have had their 'before' command run
         let mut systems_initialized: HashMap<String, ()> = HashMap::new();
@@ -102,6 +600,13 @@ impl TryFrom<SimulationRunData> for SimulationSink {
                     .find(|s| s.key == *system_key)
                     .with_context(|| format!("Could not resolve system {}", system_key))?;
 
+                if let FormatType::Csv | FormatType::Parquet = system.format.otype {
+                    bail!(
+                        "System {} has a format that is only supported for effects written to files",
+                        system_key
+                    );
+                }
+
                 #[cfg(target_os = "windows")]
                 let (shell, flag) = ("cmd", "/C");
 
@@ -109,10 +614,11 @@ impl TryFrom<SimulationRunData> for SimulationSink {
                 let (shell, flag) = ("sh", "-c");
 
                 // Run the 'before' command once per system if it exists
-                if let Some(before_command) = &system.import.before
+                if mode != OpenMode::Resume
+                    && let Some(before_command) = &system.import.before
                     && !systems_initialized.contains_key(system_key.as_str())
                 {
-                    let status = Command::new(shell)
+                    let status = std::process::Command::new(shell)
                         .arg(flag)
                         .arg(before_command)
                         .stdin(Stdio::null())
@@ -137,22 +643,6 @@ impl TryFrom<SimulationRunData> for SimulationSink {
                     systems_initialized.insert(system_key.clone(), ());
                 }
 
-                let mut child = Command::new(shell)
-                    .arg(flag)
-                    .arg(system.import.command.clone())
-                    .stdin(Stdio::piped())
-                    .stdout(Stdio::null())
-                    .stderr(Stdio::inherit())
-                    .spawn()
-                    .with_context(|| {
-                        format!(
-                            "Could not run import command for system {}:\n\n{}",
-                            system_key, system.import.command
-                        )
-                    })?;
-
-                let child_stdin = child.stdin.take().expect("No stdin");
-
                 let system_key = system_key.clone();
 
                 simulation_sink.effects.insert(
@@ -160,20 +650,117 @@ impl TryFrom<SimulationRunData> for SimulationSink {
                     Effect {
                         system_key: system_key.clone(),
                         format_type: system.format.otype.clone(),
+                        system: true,
+                        table: (system.import.sqlite.is_some()
+                            || system.import.postgres.is_some()
+                            || system.import.mysql.is_some())
+                        .then(|| table_name(effect)),
                     },
                 );
 
+                // Open a single output per system, shared by all of its effects
+                if simulation_sink.system_sinks.contains_key(&system_key) {
+                    continue;
+                }
+
+                if let Some(sqlite) = &system.import.sqlite {
+                    let sqlite = SqliteOutput::open(
+                        system_key.clone(),
+                        sqlite,
+                        simulation_sink.import_report_sender.clone(),
+                    )?;
+
+                    simulation_sink.system_sinks.insert(
+                        system_key.clone(),
+                        Output::file(system_key, FileWriter::Sqlite(Box::new(sqlite))),
+                    );
+                    continue;
+                }
+
+                if let Some(postgres) = &system.import.postgres {
+                    let postgres = PostgresOutput::connect(
+                        system_key.clone(),
+                        postgres,
+                        simulation_sink.import_report_sender.clone(),
+                    )
+                    .await?;
+
+                    simulation_sink
+                        .system_sinks
+                        .insert(system_key.clone(), Output::database(system_key, postgres));
+                    continue;
+                }
+
+                if let Some(mysql) = &system.import.mysql {
+                    let mysql = MysqlOutput::connect(
+                        system_key.clone(),
+                        mysql,
+                        simulation_sink.import_report_sender.clone(),
+                    )
+                    .await?;
+
+                    simulation_sink
+                        .system_sinks
+                        .insert(system_key.clone(), Output::database(system_key, mysql));
+                    continue;
+                }
+
+                let Some(import_command) = &system.import.command else {
+                    bail!("System {} has no import command", system_key);
+                };
+
+                let mut command = Command::new(shell);
+                command
+                    .arg(flag)
+                    .arg(import_command)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::inherit());
+
+                // Import commands get their own process group, so that Ctrl-C
+                // in a terminal only interrupts the CLI, which then closes
+                // their stdin after the last complete event
+                #[cfg(unix)]
+                command.process_group(0);
+
+                let mut child = command.spawn().with_context(|| {
+                    format!(
+                        "Could not run import command for system {}:\n\n{}",
+                        system_key, import_command
+                    )
+                })?;
+
+                let child_stdin = child.stdin.take().expect("No stdin");
+
+                simulation_sink.system_sinks.insert(
+                    system_key.clone(),
+                    Output::import(system_key.clone(), child_stdin),
+                );
+
                 simulation_sink
-                    .system_sinks
-                    .insert(system_key, Box::new(child_stdin));
-            } else if let Some(format) = &effect.format {
+                    .import_processes
+                    .push(ImportProcess { system_key, child });
+            } else if let Some(format) = &effect.format
+                && mode != OpenMode::Replay
+            {
                 let (extension, system_type) = match format.otype {
                     FormatType::Sql => ("sql", "sql"),
                     FormatType::Json => ("jsonl", "json"),
+                    FormatType::Csv => ("csv", "csv"),
+                    FormatType::Parquet => ("parquet", "parquet"),
                 };
 
                 let file_path = simulation_directory.join(format!("{}.{}", effect.key, extension));
 
+                if let FormatType::Parquet = format.otype
+                    && mode == OpenMode::Resume
+                {
+                    bail!(
+                        "Could not resume effect {}, since parquet files are only complete once a run finishes",
+                        effect.key
+                    );
+                }
+
                 let file = OpenOptions::new()
                     .create(true)
                     .append(true)
@@ -191,15 +778,113 @@ impl TryFrom<SimulationRunData> for SimulationSink {
                     Effect {
                         system_key: system_key.clone(),
                         format_type: format.otype.clone(),
+                        system: false,
+                        table: None,
                     },
                 );
 
+                let writer = match format.otype {
+                    FormatType::Csv => FileWriter::Csv(Box::new(
+                        CsvOutput::new(file, format, effect.schema.as_ref()).with_context(
+                            || format!("Could not create CSV output for effect {}", effect.key),
+                        )?,
+                    )),
+                    FormatType::Parquet => FileWriter::Parquet(Box::new(
+                        ParquetOutput::new(file, effect.schema.as_ref()).with_context(|| {
+                            format!("Could not create parquet output for effect {}", effect.key)
+                        })?,
+                    )),
+                    _ => FileWriter::Lines(BufWriter::new(file)),
+                };
+
                 simulation_sink
                     .system_sinks
-                    .insert(system_key, Box::new(BufWriter::new(file)));
+                    .insert(system_key.clone(), Output::file(system_key, writer));
+                simulation_sink.output_files.push(file_path);
             }
         }
 
         Ok(simulation_sink)
     }
 }
+
+/// The table a built-in database system inserts an effect's values into.
+fn table_name(effect: &crate::model::Effect) -> String {
+    effect
+        .format
+        .as_ref()
+        .and_then(|format| format.table.clone())
+        .or_else(|| effect.entity.clone())
+        .unwrap_or_else(|| effect.key.clone())
+}
+
+/// Waits for an import command to exit, killing it if it is still running at
+/// the deadline.
+async fn wait_until(
+    import_process: &mut ImportProcess,
+    deadline: Option<Instant>,
+) -> io::Result<ExitStatus> {
+    let Some(deadline) = deadline else {
+        return import_process.child.wait().await;
+    };
+
+    if let Ok(status) = time::timeout_at(deadline, import_process.child.wait()).await {
+        return status;
+    }
+
+    eprintln!(
+        "Import command for system {} did not exit in time, killing it",
+        import_process.system_key
+    );
+    import_process.child.kill().await?;
+    import_process.child.wait().await
+}
+
+/// Fails with a per-system report if any import command exited unsuccessfully.
+pub fn ensure_imports_succeeded(import_statuses: &[ImportStatus]) -> Result<()> {
+    let failed_imports = import_statuses
+        .iter()
+        .filter(|import_status| !import_status.status.success())
+        .map(|import_status| {
+            format!(
+                "  {}: import command failed with {}",
+                import_status.system_key, import_status.status
+            )
+        })
+        .collect::<Vec<_>>();
+
+    if !failed_imports.is_empty() {
+        bail!(
+            "Import failed for {} of {} systems\n{}",
+            failed_imports.len(),
+            import_statuses.len(),
+            failed_imports.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+/// The files a run's events were recorded to, in the order of its sessions.
+pub fn events_files(simulation_directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(simulation_directory)
+        .with_context(|| format!("Failed to read {}", simulation_directory.display()))?
+    {
+        let entry = entry?;
+
+        if let Some(session) = entry.file_name().to_str().and_then(|file_name| {
+            file_name
+                .strip_prefix(EVENTS_FILE_PREFIX)?
+                .strip_suffix(EVENTS_FILE_SUFFIX)?
+                .parse::<u64>()
+                .ok()
+        }) {
+            files.push((session, entry.path()));
+        }
+    }
+
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
@@ -0,0 +1,124 @@
+use crate::model::Format;
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+use std::fs::File;
+
+/// Writes effect values as CSV rows, one column per (flattened) property.
+pub struct CsvOutput {
+    writer: csv::Writer<File>,
+    columns: Option<Vec<String>>,
+    write_header: bool,
+    null: String,
+}
+
+impl CsvOutput {
+    /// Creates a CSV output, taking the column order from the effect's schema
+    /// if it has one, or otherwise from the first value written.
+    ///
+    /// The header row is only written if the file is empty, so that resumed
+    /// runs append rows to the existing file.
+    pub fn new(file: File, format: &Format, schema: Option<&Value>) -> Result<Self> {
+        let delimiter = match format.delimiter.as_deref() {
+            None => b',',
+            Some(delimiter) if delimiter.len() == 1 && delimiter.is_ascii() => {
+                delimiter.as_bytes()[0]
+            }
+            Some(delimiter) => bail!(
+                "CSV delimiter must be a single ASCII character, got '{}'",
+                delimiter
+            ),
+        };
+
+        let write_header = file
+            .metadata()
+            .with_context(|| "Failed to read CSV file metadata")?
+            .len()
+            == 0;
+
+        let columns = schema
+            .map(|schema| {
+                let mut columns = Vec::new();
+                schema_columns(schema, "", &mut columns);
+                columns
+            })
+            .filter(|columns| !columns.is_empty());
+
+        Ok(CsvOutput {
+            writer: csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(file),
+            columns,
+            write_header,
+            null: format.null.clone().unwrap_or_default(),
+        })
+    }
+
+    pub fn write_value(&mut self, value: &Value) -> Result<()> {
+        let mut fields = Map::new();
+        flatten(value, "", &mut fields);
+
+        let columns = self
+            .columns
+            .get_or_insert_with(|| fields.keys().cloned().collect());
+
+        if self.write_header {
+            self.writer.write_record(columns.iter())?;
+            self.write_header = false;
+        }
+
+        let record = columns.iter().map(|column| match fields.get(column) {
+            None | Some(Value::Null) => self.null.clone(),
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+        });
+
+        self.writer.write_record(record)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Collects the columns of an object schema, naming nested object properties
+/// with a dotted path.
+fn schema_columns(schema: &Value, prefix: &str, columns: &mut Vec<String>) {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return;
+    };
+
+    for (key, property) in properties {
+        let column = format!("{}{}", prefix, key);
+
+        if property
+            .get("properties")
+            .is_some_and(|properties| properties.is_object())
+        {
+            schema_columns(property, &format!("{}.", column), columns);
+        } else {
+            columns.push(column);
+        }
+    }
+}
+
+/// Flattens nested objects into dotted keys. Arrays are kept as values and
+/// rendered as JSON.
+fn flatten(value: &Value, prefix: &str, fields: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = format!("{}{}", prefix, key);
+
+                if value.is_object() {
+                    flatten(value, &format!("{}.", key), fields);
+                } else {
+                    fields.insert(key, value.clone());
+                }
+            }
+        }
+        value => {
+            fields.insert(prefix.trim_end_matches('.').to_string(), value.clone());
+        }
+    }
+}
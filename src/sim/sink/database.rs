@@ -0,0 +1,158 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use tokio::sync::mpsc;
+
+/// An event to be inserted by a built-in database system, which reports
+/// failures per event rather than through an import command's stderr.
+pub struct DatabaseEvent {
+    pub id: u64,
+    pub effect: String,
+    pub table: String,
+    pub statement: Statement,
+}
+
+pub enum Statement {
+    /// A value whose fields are inserted as the columns of a row.
+    Values(Value),
+    /// SQL rendered by the API, which is executed as is.
+    Sql(String),
+}
+
+/// What a built-in database system reports back to the sink as it commits.
+pub enum ImportReport {
+    /// An event that the database rejected, such as for a constraint
+    /// violation.
+    Error(ImportError),
+    /// Rows committed to a table.
+    Rows {
+        system: String,
+        table: String,
+        count: u64,
+    },
+}
+
+pub struct ImportError {
+    pub id: u64,
+    pub effect: String,
+    pub system: String,
+    pub message: String,
+}
+
+pub type ImportReportSender = mpsc::UnboundedSender<ImportReport>;
+
+/// A built-in database system that inserts events from an async task.
+pub trait DatabaseWriter: Send + 'static {
+    fn write_event(&mut self, event: DatabaseEvent) -> impl Future<Output = Result<()>> + Send;
+
+    /// Commits the events written so far.
+    fn flush(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    fn close(self) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl DatabaseEvent {
+    /// The fields of the event's value, which are inserted as columns.
+    pub fn columns(value: &Value) -> Result<&Map<String, Value>, String> {
+        value
+            .as_object()
+            .filter(|columns| !columns.is_empty())
+            .ok_or_else(|| "value should be an object with at least one field".to_string())
+    }
+
+    /// Reports the event as rejected by a system.
+    pub fn reject(&self, system: &str, message: impl ToString, reports: &ImportReportSender) {
+        // The run is over once its reports can't be received
+        let _ = reports.send(ImportReport::Error(ImportError {
+            id: self.id,
+            effect: self.effect.clone(),
+            system: system.to_string(),
+            message: message.to_string(),
+        }));
+    }
+}
+
+/// Counts the rows inserted into each table until they are committed.
+#[derive(Default)]
+pub struct TableRows(BTreeMap<String, u64>);
+
+impl TableRows {
+    pub fn add(&mut self, table: &str, count: u64) {
+        *self.0.entry(table.to_string()).or_default() += count;
+    }
+
+    /// Reports the counted rows as committed and starts counting again.
+    pub fn commit(&mut self, system: &str, reports: &ImportReportSender) {
+        for (table, count) in std::mem::take(&mut self.0) {
+            let _ = reports.send(ImportReport::Rows {
+                system: system.to_string(),
+                table,
+                count,
+            });
+        }
+    }
+}
+
+/// What a batch's transaction inserted and rejected, which is only reported
+/// once it is committed.
+#[derive(Default)]
+pub struct BatchOutcome {
+    pub rows: TableRows,
+    /// The index of each rejected event in the batch, with its error.
+    pub rejected: Vec<(usize, String)>,
+}
+
+impl BatchOutcome {
+    pub fn report(mut self, events: &[DatabaseEvent], system: &str, reports: &ImportReportSender) {
+        for (index, message) in self.rejected {
+            events[index].reject(system, message, reports);
+        }
+
+        self.rows.commit(system, reports);
+    }
+}
+
+/// Orders the tables of a batch so that each comes after the tables it
+/// references with foreign keys, otherwise keeping the order they were first
+/// written in. Tables that reference each other keep that order too.
+pub fn table_order(events: &[DatabaseEvent], references: impl Fn(&str, &str) -> bool) -> Vec<&str> {
+    let mut remaining: Vec<&str> = Vec::new();
+    for event in events {
+        if !remaining.contains(&event.table.as_str()) {
+            remaining.push(&event.table);
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let next = remaining
+            .iter()
+            .position(|table| {
+                remaining
+                    .iter()
+                    .all(|other| other == table || !references(table, other))
+            })
+            .unwrap_or(0);
+
+        ordered.push(remaining.remove(next));
+    }
+
+    ordered
+}
+
+/// Quotes a column name, doubling any quotes within it.
+pub fn quote_identifier(name: &str, quote: char) -> String {
+    let escaped = name.replace(quote, &format!("{quote}{quote}"));
+    format!("{quote}{escaped}{quote}")
+}
+
+/// Quotes a table name, which may be qualified by a schema such as
+/// `public.users`.
+pub fn quote_table(name: &str, quote: char) -> String {
+    name.split('.')
+        .map(|part| quote_identifier(part, quote))
+        .collect::<Vec<_>>()
+        .join(".")
+}
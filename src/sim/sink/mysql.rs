@@ -0,0 +1,508 @@
+use super::database::{
+    BatchOutcome, DatabaseEvent, DatabaseWriter, ImportReportSender, Statement, quote_identifier,
+    quote_table, table_order,
+};
+use crate::model::MysqlImport;
+use anyhow::{Context, Result, bail};
+use mysql_async::prelude::Queryable;
+use mysql_async::{Conn, Opts, Transaction, TxOpts, Value as SqlValue};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+
+/// The number of events inserted per transaction.
+const BATCH_SIZE: usize = 5_000;
+
+/// The most rows inserted by a single `INSERT` statement.
+const ROWS_PER_INSERT: usize = 500;
+
+/// The most placeholders MySQL allows in a prepared statement.
+const MAX_PLACEHOLDERS: usize = 65_535;
+
+/// The environment variable holding the connection string when a system
+/// doesn't set one.
+const DEFAULT_URL_ENV: &str = "DATABASE_URL";
+
+/// Inserts the events of a system into a MySQL or MariaDB database.
+///
+/// Events are buffered into batches, and each batch is committed in a single
+/// transaction with multi-row `INSERT` statements per table, ordered so that
+/// tables referenced by foreign keys are inserted first. A batch that fails
+/// is inserted again row by row, reporting the events that are rejected on
+/// their own.
+///
+/// Foreign key checks can instead be disabled while loading, in which case
+/// the foreign keys of the tables written to are verified once the load is
+/// done.
+pub struct MysqlOutput {
+    system_key: String,
+    conn: Conn,
+    reports: ImportReportSender,
+    /// The database that unqualified table names resolve to.
+    database: String,
+    foreign_keys: Vec<ForeignKey>,
+    disable_foreign_key_checks: bool,
+    /// The tables written to, by qualified name.
+    written: HashSet<String>,
+    batch: Vec<DatabaseEvent>,
+}
+
+/// A foreign key between tables, by qualified name.
+struct ForeignKey {
+    name: String,
+    table: String,
+    columns: Vec<String>,
+    referenced_table: String,
+    referenced_columns: Vec<String>,
+}
+
+/// The rows of a batch that are inserted into a table with the same columns.
+struct InsertGroup<'a> {
+    table: &'a str,
+    columns: Vec<&'a str>,
+    values: Vec<SqlValue>,
+}
+
+impl MysqlOutput {
+    pub async fn connect(
+        system_key: String,
+        import: &MysqlImport,
+        reports: ImportReportSender,
+    ) -> Result<Self> {
+        let url = match &import.url {
+            Some(url) => url.clone(),
+            None => {
+                let url_env = import.url_env.as_deref().unwrap_or(DEFAULT_URL_ENV);
+                env::var(url_env).with_context(|| {
+                    format!(
+                        "Could not find a connection string for system {}, set import.mysql.url or {}",
+                        system_key, url_env
+                    )
+                })?
+            }
+        };
+
+        let opts = Opts::from_url(&url).with_context(|| {
+            format!("Invalid MySQL connection string for system {}", system_key)
+        })?;
+
+        let mut conn = Conn::new(opts)
+            .await
+            .with_context(|| format!("Could not connect to MySQL for system {}", system_key))?;
+
+        let Some(database) = conn
+            .query_first::<Option<String>, _>("SELECT DATABASE()")
+            .await?
+            .flatten()
+        else {
+            bail!(
+                "The connection string for system {} should name a database",
+                system_key
+            );
+        };
+
+        let mut foreign_keys: Vec<ForeignKey> = Vec::new();
+
+        for (name, schema, table, column, referenced_schema, referenced_table, referenced_column) in
+            conn.query::<(String, String, String, String, String, String, String), _>(
+                "SELECT CONSTRAINT_NAME, TABLE_SCHEMA, TABLE_NAME, COLUMN_NAME,
+                    REFERENCED_TABLE_SCHEMA, REFERENCED_TABLE_NAME, REFERENCED_COLUMN_NAME
+                FROM information_schema.KEY_COLUMN_USAGE
+                WHERE TABLE_SCHEMA = DATABASE() AND REFERENCED_TABLE_NAME IS NOT NULL
+                ORDER BY TABLE_NAME, CONSTRAINT_NAME, ORDINAL_POSITION",
+            )
+            .await
+            .with_context(|| format!("Failed to read foreign keys for system {}", system_key))?
+        {
+            let table = format!("{}.{}", schema, table);
+
+            // The columns of a composite key are listed in consecutive rows
+            match foreign_keys.last_mut() {
+                Some(foreign_key) if foreign_key.table == table && foreign_key.name == name => {
+                    foreign_key.columns.push(column);
+                    foreign_key.referenced_columns.push(referenced_column);
+                }
+                _ => foreign_keys.push(ForeignKey {
+                    name,
+                    table,
+                    columns: vec![column],
+                    referenced_table: format!("{}.{}", referenced_schema, referenced_table),
+                    referenced_columns: vec![referenced_column],
+                }),
+            }
+        }
+
+        if import.disable_foreign_key_checks {
+            conn.query_drop("SET FOREIGN_KEY_CHECKS = 0").await?;
+        }
+
+        Ok(MysqlOutput {
+            system_key,
+            conn,
+            reports,
+            database,
+            foreign_keys,
+            disable_foreign_key_checks: import.disable_foreign_key_checks,
+            written: HashSet::new(),
+            batch: Vec::new(),
+        })
+    }
+
+    fn qualify(&self, table: &str) -> String {
+        if table.contains('.') {
+            table.to_string()
+        } else {
+            format!("{}.{}", self.database, table)
+        }
+    }
+
+    /// Commits the buffered events, reporting the rows inserted into each
+    /// table and the events that were rejected. The events are kept if the
+    /// batch can't be committed at all, such as when the connection is lost.
+    async fn commit(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let events = std::mem::take(&mut self.batch);
+        let tables = table_order(&events, |table, other| {
+            let (table, other) = (self.qualify(table), self.qualify(other));
+            self.foreign_keys.iter().any(|foreign_key| {
+                foreign_key.table == table && foreign_key.referenced_table == other
+            })
+        });
+
+        for table in &tables {
+            self.written.insert(self.qualify(table));
+        }
+
+        let outcome = match insert_batch(&mut self.conn, &events, &tables).await {
+            Ok(outcome) => outcome,
+            // The failing rows are found by inserting them one at a time
+            Err(_) => match insert_each(&mut self.conn, &events, &tables).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    self.batch = events;
+                    return Err(e).with_context(|| {
+                        format!("Failed to commit to MySQL for system {}", self.system_key)
+                    });
+                }
+            },
+        };
+
+        outcome.report(&events, &self.system_key, &self.reports);
+        Ok(())
+    }
+
+    /// Enables foreign key checks again and looks for rows that were loaded
+    /// without the rows they reference, since MySQL doesn't check the
+    /// existing rows of a table when the checks are enabled.
+    async fn verify_foreign_keys(&mut self) -> Result<()> {
+        self.conn.query_drop("SET FOREIGN_KEY_CHECKS = 1").await?;
+
+        let mut violations = Vec::new();
+
+        for foreign_key in &self.foreign_keys {
+            if !self.written.contains(&foreign_key.table) {
+                continue;
+            }
+
+            let count = self
+                .conn
+                .query_first::<u64, _>(foreign_key.orphans_query())
+                .await
+                .with_context(|| format!("Failed to verify foreign key {}", foreign_key.name))?
+                .unwrap_or_default();
+
+            if count > 0 {
+                violations.push(format!(
+                    "{} rows of {} reference missing rows of {} ({})",
+                    count, foreign_key.table, foreign_key.referenced_table, foreign_key.name
+                ));
+            }
+        }
+
+        if !violations.is_empty() {
+            bail!(
+                "Foreign keys of system {} are violated:\n  {}",
+                self.system_key,
+                violations.join("\n  ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl ForeignKey {
+    /// Counts the rows whose key columns are all set but don't match a row
+    /// of the referenced table.
+    fn orphans_query(&self) -> String {
+        let join = self
+            .columns
+            .iter()
+            .zip(&self.referenced_columns)
+            .map(|(column, referenced_column)| {
+                format!(
+                    "t.{} = r.{}",
+                    quote_identifier(column, '`'),
+                    quote_identifier(referenced_column, '`')
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        let set = self
+            .columns
+            .iter()
+            .map(|column| format!("t.{} IS NOT NULL", quote_identifier(column, '`')))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        format!(
+            "SELECT COUNT(*) FROM {} AS t LEFT JOIN {} AS r ON {} WHERE r.{} IS NULL AND {}",
+            quote_table(&self.table, '`'),
+            quote_table(&self.referenced_table, '`'),
+            join,
+            quote_identifier(&self.referenced_columns[0], '`'),
+            set
+        )
+    }
+}
+
+impl DatabaseWriter for MysqlOutput {
+    async fn write_event(&mut self, event: DatabaseEvent) -> Result<()> {
+        if let Statement::Values(value) = &event.statement
+            && let Err(message) = DatabaseEvent::columns(value)
+        {
+            event.reject(&self.system_key, message, &self.reports);
+            return Ok(());
+        }
+
+        self.batch.push(event);
+
+        if self.batch.len() >= BATCH_SIZE {
+            self.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.commit().await
+    }
+
+    async fn close(mut self) -> Result<()> {
+        self.commit().await?;
+
+        if self.disable_foreign_key_checks {
+            self.verify_foreign_keys().await?;
+        }
+
+        self.conn
+            .disconnect()
+            .await
+            .with_context(|| format!("Failed to close MySQL for system {}", self.system_key))
+    }
+}
+
+/// Inserts a batch in a single transaction with multi-row `INSERT`
+/// statements, failing as a whole if any of its rows are rejected. Rendered
+/// SQL is executed after the inserted rows.
+async fn insert_batch(
+    conn: &mut Conn,
+    events: &[DatabaseEvent],
+    tables: &[&str],
+) -> Result<BatchOutcome> {
+    let mut groups: Vec<InsertGroup> = Vec::new();
+
+    for event in events {
+        let Statement::Values(value) = &event.statement else {
+            continue;
+        };
+        let Ok(columns) = DatabaseEvent::columns(value) else {
+            continue;
+        };
+
+        let group = match groups.iter().position(|group| {
+            group.table == event.table
+                && group
+                    .columns
+                    .iter()
+                    .copied()
+                    .eq(columns.keys().map(String::as_str))
+        }) {
+            Some(index) => &mut groups[index],
+            None => {
+                groups.push(InsertGroup {
+                    table: &event.table,
+                    columns: columns.keys().map(String::as_str).collect(),
+                    values: Vec::new(),
+                });
+                groups.last_mut().expect("Group was just added")
+            }
+        };
+
+        group.values.extend(columns.values().map(sql_value));
+    }
+
+    groups.sort_by_key(|group| tables.iter().position(|table| *table == group.table));
+
+    let mut outcome = BatchOutcome::default();
+    let mut transaction = conn.start_transaction(TxOpts::default()).await?;
+
+    for group in groups {
+        let rows_per_insert = ROWS_PER_INSERT.min(MAX_PLACEHOLDERS / group.columns.len());
+
+        for values in group.values.chunks(rows_per_insert * group.columns.len()) {
+            let rows = values.len() / group.columns.len();
+
+            transaction
+                .exec_drop(
+                    insert_sql(group.table, &group.columns, rows),
+                    values.to_vec(),
+                )
+                .await?;
+
+            outcome.rows.add(group.table, rows as u64);
+        }
+    }
+
+    // Rendered SQL follows the same table order as the inserted rows
+    for table in tables {
+        for (index, event) in events.iter().enumerate() {
+            if let Statement::Sql(sql) = &event.statement
+                && event.table == *table
+            {
+                execute(&mut transaction, index, event, sql, None, &mut outcome).await?;
+            }
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+/// Inserts a batch one event at a time within a single transaction, so that
+/// only the events that fail are rejected.
+async fn insert_each(
+    conn: &mut Conn,
+    events: &[DatabaseEvent],
+    tables: &[&str],
+) -> Result<BatchOutcome> {
+    let mut outcome = BatchOutcome::default();
+    let mut transaction = conn.start_transaction(TxOpts::default()).await?;
+
+    for table in tables {
+        for (index, event) in events.iter().enumerate() {
+            if event.table != *table {
+                continue;
+            }
+
+            match &event.statement {
+                Statement::Values(value) => {
+                    let Ok(columns) = DatabaseEvent::columns(value) else {
+                        continue;
+                    };
+
+                    let sql = insert_sql(
+                        table,
+                        &columns.keys().map(String::as_str).collect::<Vec<_>>(),
+                        1,
+                    );
+                    let values = columns.values().map(sql_value).collect();
+
+                    execute(
+                        &mut transaction,
+                        index,
+                        event,
+                        &sql,
+                        Some(values),
+                        &mut outcome,
+                    )
+                    .await?;
+                }
+                Statement::Sql(sql) => {
+                    execute(&mut transaction, index, event, sql, None, &mut outcome).await?;
+                }
+            }
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+/// Executes the SQL of an event within a savepoint, so that a statement
+/// failing partway through doesn't leave the event partially applied.
+/// Values are bound to a prepared statement, while rendered SQL is executed
+/// as is, since it may hold several statements.
+async fn execute(
+    transaction: &mut Transaction<'_>,
+    index: usize,
+    event: &DatabaseEvent,
+    sql: &str,
+    values: Option<Vec<SqlValue>>,
+    outcome: &mut BatchOutcome,
+) -> Result<()> {
+    transaction.query_drop("SAVEPOINT event").await?;
+
+    let result = match values {
+        Some(values) => transaction.exec_drop(sql, values).await,
+        None => transaction.query_drop(sql).await,
+    };
+
+    match result {
+        Ok(()) => {
+            transaction.query_drop("RELEASE SAVEPOINT event").await?;
+            outcome.rows.add(&event.table, 1);
+        }
+        Err(e) => {
+            transaction
+                .query_drop("ROLLBACK TO SAVEPOINT event")
+                .await?;
+            outcome.rejected.push((index, error_message(&e)));
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_sql(table: &str, columns: &[&str], rows: usize) -> String {
+    let row = format!("({})", vec!["?"; columns.len()].join(", "));
+
+    format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        quote_table(table, '`'),
+        columns
+            .iter()
+            .map(|column| quote_identifier(column, '`'))
+            .collect::<Vec<_>>()
+            .join(", "),
+        vec![row; rows].join(", ")
+    )
+}
+
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::NULL,
+        Value::Bool(b) => SqlValue::Int(*b as i64),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => SqlValue::Int(i),
+            (None, Some(u)) => SqlValue::UInt(u),
+            (None, None) => SqlValue::Double(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Bytes(s.clone().into_bytes()),
+        // Nested values are stored as JSON, for JSON columns
+        Value::Array(_) | Value::Object(_) => SqlValue::Bytes(value.to_string().into_bytes()),
+    }
+}
+
+/// The message of a database error, without the code and state that
+/// `mysql_async::Error` renders around it.
+fn error_message(error: &mysql_async::Error) -> String {
+    match error {
+        mysql_async::Error::Server(server_error) => server_error.message.clone(),
+        error => error.to_string(),
+    }
+}
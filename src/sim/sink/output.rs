@@ -0,0 +1,313 @@
+use super::csv::CsvOutput;
+use super::database::{DatabaseEvent, DatabaseWriter};
+use super::parquet::ParquetOutput;
+use super::sqlite::SqliteOutput;
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::thread;
+use tokio::io::{AsyncWriteExt, BufWriter as AsyncBufWriter};
+use tokio::process::ChildStdin;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Instant};
+
+/// The number of rows queued for an output before writing to it waits for
+/// the output to catch up.
+const BUFFER_SIZE: usize = 1024;
+
+/// A formatted event, as written to an output.
+pub enum Row {
+    /// A line of JSON or SQL.
+    Line(String),
+    /// A value to be written as a CSV row or Parquet record.
+    Value(Value),
+    /// An event to be inserted by a built-in database system.
+    Event(DatabaseEvent),
+}
+
+enum Message {
+    Row(Row),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+/// A file written by an output, including the database file of a built-in
+/// SQLite system.
+pub enum FileWriter {
+    Lines(BufWriter<File>),
+    Csv(Box<CsvOutput>),
+    Parquet(Box<ParquetOutput>),
+    Sqlite(Box<SqliteOutput>),
+}
+
+/// Where the events of a system or file-based effect are written.
+///
+/// Each output is drained by its own task through a bounded channel, so a
+/// slow import command only holds back the rows queued for it, and the
+/// stream only waits once that queue is full.
+pub struct Output {
+    key: String,
+    sender: mpsc::Sender<Message>,
+    task: JoinHandle<Result<()>>,
+    /// The id of the last event queued for the output.
+    queued: Option<u64>,
+    /// The id of the last event the output is known to have flushed.
+    flushed: Option<u64>,
+}
+
+impl Output {
+    /// Writes lines to the stdin of an import command as it reads them.
+    pub fn import(key: String, stdin: ChildStdin) -> Self {
+        let (sender, mut receiver) = mpsc::channel(BUFFER_SIZE);
+
+        let task = tokio::spawn(async move {
+            let mut stdin = AsyncBufWriter::new(stdin);
+
+            // A broken pipe means the import command exited early, which is
+            // reported through its exit status, so the remaining rows are
+            // drained without being written
+            let mut exited = false;
+
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    Message::Row(Row::Line(line)) if !exited => {
+                        let result = async {
+                            stdin.write_all(line.as_bytes()).await?;
+                            stdin.write_all(b"\n").await
+                        }
+                        .await;
+
+                        exited = ignore_broken_pipe(result)?;
+                    }
+                    Message::Row(_) => {}
+                    Message::Flush(reply) => {
+                        let result = if exited { Ok(()) } else { stdin.flush().await };
+
+                        let _ = reply.send(result.or_else(|e| match e.kind() {
+                            io::ErrorKind::BrokenPipe => Ok(()),
+                            _ => Err(e),
+                        }));
+                    }
+                }
+            }
+
+            // Shutting down stdin closes it, signalling the end of the input
+            if !exited {
+                ignore_broken_pipe(stdin.shutdown().await)?;
+            }
+
+            Ok(())
+        });
+
+        Output::new(key, sender, task)
+    }
+
+    /// Writes to a file on a thread of its own, rather than one from the
+    /// runtime's blocking pool, which it would hold for the whole run.
+    ///
+    /// Like a database output, it fails every later flush and the close once
+    /// a row can't be written or a flush fails, such as when a SQLite
+    /// transaction can't be committed.
+    pub fn file(key: String, mut writer: FileWriter) -> Self {
+        let (sender, mut receiver) = mpsc::channel(BUFFER_SIZE);
+        let (done, result) = oneshot::channel();
+        let output_key = key.clone();
+
+        thread::spawn(move || {
+            let mut failure: Option<anyhow::Error> = None;
+
+            while let Some(message) = receiver.blocking_recv() {
+                match message {
+                    Message::Row(row) if failure.is_none() => {
+                        if let Err(e) = writer.write_row(row) {
+                            failure =
+                                Some(e.context(format!("Failed to write row for {}", output_key)));
+                        }
+                    }
+                    Message::Row(_) => {}
+                    Message::Flush(reply) => {
+                        if failure.is_none()
+                            && let Err(e) = writer.flush()
+                        {
+                            failure = Some(e.into());
+                        }
+
+                        let _ = reply.send(match &failure {
+                            Some(e) => Err(io::Error::other(format!("{:#}", e))),
+                            None => Ok(()),
+                        });
+                    }
+                }
+            }
+
+            let _ = done.send(match failure {
+                Some(e) => Err(e),
+                None => writer.close(),
+            });
+        });
+
+        let task = tokio::spawn(async move {
+            result
+                .await
+                .map_err(|_| anyhow!("Output thread stopped without closing the file"))
+                .and_then(|result| result)
+        });
+
+        Output::new(key, sender, task)
+    }
+
+    /// Inserts events into a database over an async connection.
+    ///
+    /// Once a batch fails to commit, the remaining rows are drained without
+    /// being written, and every later flush and the close fail with the
+    /// error, so that the run isn't checkpointed past the lost rows.
+    pub fn database<W: DatabaseWriter>(key: String, mut writer: W) -> Self {
+        let (sender, mut receiver) = mpsc::channel(BUFFER_SIZE);
+
+        let task = tokio::spawn(async move {
+            let mut failure: Option<anyhow::Error> = None;
+
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    Message::Row(Row::Event(event)) if failure.is_none() => {
+                        if let Err(e) = writer.write_event(event).await {
+                            failure = Some(e);
+                        }
+                    }
+                    Message::Row(_) => {}
+                    Message::Flush(reply) => {
+                        if failure.is_none()
+                            && let Err(e) = writer.flush().await
+                        {
+                            failure = Some(e);
+                        }
+
+                        let _ = reply.send(match &failure {
+                            Some(e) => Err(io::Error::other(format!("{:#}", e))),
+                            None => Ok(()),
+                        });
+                    }
+                }
+            }
+
+            match failure {
+                Some(e) => Err(e),
+                None => writer.close().await,
+            }
+        });
+
+        Output::new(key, sender, task)
+    }
+
+    fn new(key: String, sender: mpsc::Sender<Message>, task: JoinHandle<Result<()>>) -> Self {
+        Output {
+            key,
+            sender,
+            task,
+            queued: None,
+            flushed: None,
+        }
+    }
+
+    /// Queues the row of an event, waiting while the output's queue is full.
+    pub async fn write(&mut self, id: u64, row: Row) {
+        // The task only stops early after a failure, which `close` reports
+        if self.sender.send(Message::Row(row)).await.is_ok() {
+            self.queued = Some(id);
+        }
+    }
+
+    /// Waits until every queued row has been written and flushed.
+    pub async fn flush(&mut self) -> Result<()> {
+        let (reply, response) = oneshot::channel();
+
+        if self.sender.send(Message::Flush(reply)).await.is_err() {
+            return Ok(());
+        }
+
+        if let Ok(result) = response.await {
+            result.with_context(|| format!("Failed to flush output for {}", self.key))?;
+            self.flushed = self.queued;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the remaining rows and closes the output, giving up on the
+    /// rows still queued at the deadline. Returns the id of the last event
+    /// the output is known to have written.
+    pub async fn close(self, deadline: Option<Instant>) -> Result<Option<u64>> {
+        drop(self.sender);
+
+        let mut task = self.task;
+
+        let result = match deadline {
+            Some(deadline) => match time::timeout_at(deadline, &mut task).await {
+                Ok(result) => result,
+                Err(_) => {
+                    eprintln!(
+                        "Output for {} did not finish in time, dropping its remaining rows",
+                        self.key
+                    );
+                    task.abort();
+                    return Ok(self.flushed);
+                }
+            },
+            None => task.await,
+        };
+
+        result
+            .map_err(|e| anyhow!(e))
+            .and_then(|result| result)
+            .with_context(|| format!("Failed to close output for {}", self.key))?;
+
+        Ok(self.queued)
+    }
+}
+
+impl FileWriter {
+    fn write_row(&mut self, row: Row) -> Result<()> {
+        match (self, row) {
+            (FileWriter::Lines(writer), Row::Line(line)) => writeln!(writer, "{}", line)?,
+            (FileWriter::Lines(writer), Row::Value(value)) => writeln!(writer, "{}", value)?,
+            (FileWriter::Csv(csv), Row::Value(value)) => csv.write_value(&value)?,
+            (FileWriter::Parquet(parquet), Row::Value(value)) => parquet.write_value(value)?,
+            (FileWriter::Sqlite(sqlite), Row::Event(event)) => sqlite.write_event(event)?,
+            // Effects are only routed to outputs that can write their format
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Flushes buffered rows. Parquet files are only written once closed.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Lines(writer) => writer.flush(),
+            FileWriter::Csv(csv) => csv.flush(),
+            FileWriter::Parquet(_) => Ok(()),
+            FileWriter::Sqlite(sqlite) => sqlite.flush(),
+        }
+    }
+
+    fn close(mut self) -> Result<()> {
+        self.flush()?;
+
+        match self {
+            FileWriter::Parquet(parquet) => parquet.close(),
+            FileWriter::Sqlite(sqlite) => sqlite.close(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Treats a broken pipe as the import command having exited, returning
+/// whether it has.
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<bool> {
+    match result {
+        Ok(()) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(true),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context, Result, anyhow};
+use arrow_json::reader::{Decoder, ReaderBuilder};
+use arrow_schema::{DataType, Field, Fields, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde_json::Value;
+use std::fs::File;
+use std::sync::Arc;
+
+/// The number of values buffered before they are written as a record batch.
+const BATCH_SIZE: usize = 8192;
+
+/// Writes effect values to a Parquet file, with columns derived from the
+/// effect's schema.
+///
+/// Parquet files can't be appended to, so the file is only readable once the
+/// output is closed at the end of the run.
+pub struct ParquetOutput {
+    decoder: Decoder,
+    writer: ArrowWriter<File>,
+    values: Vec<Value>,
+}
+
+impl ParquetOutput {
+    pub fn new(file: File, schema: Option<&Value>) -> Result<Self> {
+        let schema = schema.ok_or_else(|| anyhow!("The parquet format requires a schema"))?;
+
+        let fields = object_fields(schema)
+            .filter(|fields| !fields.is_empty())
+            .ok_or_else(|| anyhow!("The parquet format requires an object schema"))?;
+
+        let schema = Arc::new(Schema::new(fields));
+
+        // Values whose type can't be derived from the schema are stored as
+        // strings, so numbers and booleans are coerced into those columns
+        let decoder = ReaderBuilder::new(schema.clone())
+            .with_batch_size(BATCH_SIZE)
+            .with_coerce_primitive(true)
+            .build_decoder()?;
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+
+        Ok(ParquetOutput {
+            decoder,
+            writer: ArrowWriter::try_new(file, schema, Some(properties))?,
+            values: Vec::with_capacity(BATCH_SIZE),
+        })
+    }
+
+    pub fn write_value(&mut self, value: Value) -> Result<()> {
+        self.values.push(value);
+
+        if self.values.len() >= BATCH_SIZE {
+            self.write_batch()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes any buffered values and the Parquet footer.
+    pub fn close(mut self) -> Result<()> {
+        self.write_batch()?;
+        self.writer
+            .close()
+            .with_context(|| "Failed to write parquet footer")?;
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        if self.values.is_empty() {
+            return Ok(());
+        }
+
+        // The buffer is cleared even when a value fails to serialize, so one
+        // bad value doesn't fail every batch after it
+        let values = std::mem::take(&mut self.values);
+        self.decoder
+            .serialize(&values)
+            .with_context(|| "Failed to convert values to parquet records")?;
+
+        if let Some(batch) = self.decoder.flush()? {
+            self.writer.write(&batch)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Derives Arrow fields from the properties of an object schema.
+fn object_fields(schema: &Value) -> Option<Fields> {
+    let properties = schema.get("properties")?.as_object()?;
+
+    Some(
+        properties
+            .iter()
+            .map(|(key, property)| Field::new(key, data_type(property), true))
+            .collect(),
+    )
+}
+
+/// Maps a schema type to an Arrow data type, falling back to strings for
+/// types like `function` whose output type isn't declared.
+fn data_type(schema: &Value) -> DataType {
+    let schema_type = schema.get("type").and_then(Value::as_str).unwrap_or("");
+
+    // Types like `id.integer` are specializations of their last segment
+    match schema_type.rsplit('.').next().unwrap_or(schema_type) {
+        "integer" => DataType::Int64,
+        "number" => DataType::Float64,
+        "boolean" => DataType::Boolean,
+        "object" => match object_fields(schema) {
+            Some(fields) if !fields.is_empty() => DataType::Struct(fields),
+            _ => DataType::Utf8,
+        },
+        "array" => {
+            let item_type = schema.get("items").map(data_type).unwrap_or(DataType::Utf8);
+            DataType::List(Arc::new(Field::new_list_field(item_type, true)))
+        }
+        _ => DataType::Utf8,
+    }
+}
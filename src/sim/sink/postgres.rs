@@ -0,0 +1,416 @@
+use super::database::{
+    BatchOutcome, DatabaseEvent, DatabaseWriter, ImportReportSender, Statement, quote_identifier,
+    quote_table, table_order,
+};
+use crate::model::PostgresImport;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::SinkExt;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::Arc;
+use tokio_postgres::{Client, Transaction};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+/// The number of events copied per transaction.
+const BATCH_SIZE: usize = 10_000;
+
+/// The environment variable holding the connection string when a system
+/// doesn't set one.
+const DEFAULT_URL_ENV: &str = "DATABASE_URL";
+
+/// Copies the events of a system into a Postgres database.
+///
+/// Events are buffered into batches, and each batch is committed in a single
+/// transaction with one `COPY ... FROM STDIN` per table, ordered so that
+/// tables referenced by foreign keys are copied first. A COPY fails as a
+/// whole, so a batch that fails is inserted again row by row, reporting the
+/// events that are rejected on their own.
+pub struct PostgresOutput {
+    system_key: String,
+    client: Client,
+    reports: ImportReportSender,
+    /// The schema that unqualified table names resolve to.
+    schema: String,
+    /// The tables referenced by each table's foreign keys, by qualified name.
+    references: HashMap<String, HashSet<String>>,
+    batch: Vec<DatabaseEvent>,
+}
+
+/// The rows of a batch that are copied into a table with the same columns.
+struct CopyGroup<'a> {
+    table: &'a str,
+    columns: Vec<&'a str>,
+    data: Vec<u8>,
+}
+
+impl PostgresOutput {
+    pub async fn connect(
+        system_key: String,
+        import: &PostgresImport,
+        reports: ImportReportSender,
+    ) -> Result<Self> {
+        let url = match &import.url {
+            Some(url) => url.clone(),
+            None => {
+                let url_env = import.url_env.as_deref().unwrap_or(DEFAULT_URL_ENV);
+                env::var(url_env).with_context(|| {
+                    format!(
+                        "Could not find a connection string for system {}, set import.postgres.url or {}",
+                        system_key, url_env
+                    )
+                })?
+            }
+        };
+
+        let (client, connection) = tokio_postgres::connect(&url, MakeRustlsConnect::new(tls()?))
+            .await
+            .with_context(|| format!("Could not connect to Postgres for system {}", system_key))?;
+
+        let connection_key = system_key.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                eprintln!(
+                    "Postgres connection for system {} failed: {}",
+                    connection_key, e
+                );
+            }
+        });
+
+        let schema = client
+            .query_one("SELECT current_schema()", &[])
+            .await?
+            .get::<_, Option<String>>(0)
+            .unwrap_or_else(|| "public".into());
+
+        let mut references: HashMap<String, HashSet<String>> = HashMap::new();
+
+        for row in client
+            .query(
+                "SELECT tn.nspname, t.relname, rn.nspname, r.relname
+                FROM pg_constraint c
+                JOIN pg_class t ON t.oid = c.conrelid
+                JOIN pg_namespace tn ON tn.oid = t.relnamespace
+                JOIN pg_class r ON r.oid = c.confrelid
+                JOIN pg_namespace rn ON rn.oid = r.relnamespace
+                WHERE c.contype = 'f' AND c.conrelid <> c.confrelid",
+                &[],
+            )
+            .await
+            .with_context(|| format!("Failed to read foreign keys for system {}", system_key))?
+        {
+            references
+                .entry(format!(
+                    "{}.{}",
+                    row.get::<_, &str>(0),
+                    row.get::<_, &str>(1)
+                ))
+                .or_default()
+                .insert(format!(
+                    "{}.{}",
+                    row.get::<_, &str>(2),
+                    row.get::<_, &str>(3)
+                ));
+        }
+
+        Ok(PostgresOutput {
+            system_key,
+            client,
+            reports,
+            schema,
+            references,
+            batch: Vec::new(),
+        })
+    }
+
+    fn qualify(&self, table: &str) -> String {
+        if table.contains('.') {
+            table.to_string()
+        } else {
+            format!("{}.{}", self.schema, table)
+        }
+    }
+
+    /// Commits the buffered events, reporting the rows copied into each
+    /// table and the events that were rejected. The events are kept if the
+    /// batch can't be committed at all, such as when the connection is lost.
+    async fn commit(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let events = std::mem::take(&mut self.batch);
+        let tables = table_order(&events, |table, other| {
+            self.references
+                .get(&self.qualify(table))
+                .is_some_and(|references| references.contains(&self.qualify(other)))
+        });
+
+        let outcome = match copy(&mut self.client, &events, &tables).await {
+            Ok(outcome) => outcome,
+            // The failing rows are found by inserting them one at a time
+            Err(_) => match insert_each(&mut self.client, &events, &tables).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    self.batch = events;
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Failed to commit to Postgres for system {}",
+                            self.system_key
+                        )
+                    });
+                }
+            },
+        };
+
+        outcome.report(&events, &self.system_key, &self.reports);
+        Ok(())
+    }
+}
+
+impl DatabaseWriter for PostgresOutput {
+    async fn write_event(&mut self, event: DatabaseEvent) -> Result<()> {
+        if let Statement::Values(value) = &event.statement
+            && let Err(message) = DatabaseEvent::columns(value)
+        {
+            event.reject(&self.system_key, message, &self.reports);
+            return Ok(());
+        }
+
+        self.batch.push(event);
+
+        if self.batch.len() >= BATCH_SIZE {
+            self.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.commit().await
+    }
+
+    async fn close(mut self) -> Result<()> {
+        self.commit().await
+    }
+}
+
+/// Copies a batch in a single transaction, which fails as a whole if any of
+/// its rows are rejected. Rendered SQL is executed after the copied rows.
+async fn copy(
+    client: &mut Client,
+    events: &[DatabaseEvent],
+    tables: &[&str],
+) -> Result<BatchOutcome> {
+    let mut groups: Vec<CopyGroup> = Vec::new();
+
+    for event in events {
+        let Statement::Values(value) = &event.statement else {
+            continue;
+        };
+        let Ok(columns) = DatabaseEvent::columns(value) else {
+            continue;
+        };
+
+        let group = match groups.iter().position(|group| {
+            group.table == event.table
+                && group
+                    .columns
+                    .iter()
+                    .copied()
+                    .eq(columns.keys().map(String::as_str))
+        }) {
+            Some(index) => &mut groups[index],
+            None => {
+                groups.push(CopyGroup {
+                    table: &event.table,
+                    columns: columns.keys().map(String::as_str).collect(),
+                    data: Vec::new(),
+                });
+                groups.last_mut().expect("Group was just added")
+            }
+        };
+
+        for (index, value) in columns.values().enumerate() {
+            if index > 0 {
+                group.data.push(b'\t');
+            }
+            write_copy_field(value, &mut group.data);
+        }
+        group.data.push(b'\n');
+    }
+
+    groups.sort_by_key(|group| tables.iter().position(|table| *table == group.table));
+
+    let mut outcome = BatchOutcome::default();
+    let mut transaction = client.transaction().await?;
+
+    for group in groups {
+        let sql = format!(
+            "COPY {} ({}) FROM STDIN",
+            quote_table(group.table, '"'),
+            group
+                .columns
+                .iter()
+                .map(|column| quote_identifier(column, '"'))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let sink = transaction.copy_in(&sql).await?;
+        futures::pin_mut!(sink);
+        sink.send(Bytes::from(group.data)).await?;
+        let count = sink.finish().await?;
+
+        outcome.rows.add(group.table, count);
+    }
+
+    // Rendered SQL follows the same table order as the copied rows
+    for table in tables {
+        for (index, event) in events.iter().enumerate() {
+            if let Statement::Sql(sql) = &event.statement
+                && event.table == *table
+            {
+                execute(&mut transaction, index, event, sql, &mut outcome).await?;
+            }
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+/// Inserts a batch one event at a time within a single transaction, so that
+/// only the events that fail are rejected.
+async fn insert_each(
+    client: &mut Client,
+    events: &[DatabaseEvent],
+    tables: &[&str],
+) -> Result<BatchOutcome> {
+    let mut outcome = BatchOutcome::default();
+    let mut transaction = client.transaction().await?;
+
+    for table in tables {
+        for (index, event) in events.iter().enumerate() {
+            if event.table != *table {
+                continue;
+            }
+
+            let sql = match &event.statement {
+                Statement::Values(value) => match DatabaseEvent::columns(value) {
+                    Ok(columns) => Cow::Owned(format!(
+                        "INSERT INTO {} ({}) VALUES ({})",
+                        quote_table(table, '"'),
+                        columns
+                            .keys()
+                            .map(|column| quote_identifier(column, '"'))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        columns
+                            .values()
+                            .map(|value| match field_text(value) {
+                                Some(text) => format!("'{}'", text.replace('\'', "''")),
+                                None => "NULL".into(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )),
+                    Err(_) => continue,
+                },
+                Statement::Sql(sql) => Cow::Borrowed(sql.as_str()),
+            };
+
+            execute(&mut transaction, index, event, &sql, &mut outcome).await?;
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+/// Executes the SQL of an event within a savepoint, so that a statement
+/// failing partway through doesn't leave the event partially applied or
+/// abort the rest of the transaction.
+async fn execute(
+    transaction: &mut Transaction<'_>,
+    index: usize,
+    event: &DatabaseEvent,
+    sql: &str,
+    outcome: &mut BatchOutcome,
+) -> Result<()> {
+    let savepoint = transaction.savepoint("event").await?;
+
+    match savepoint.batch_execute(sql).await {
+        Ok(()) => {
+            savepoint.commit().await?;
+            outcome.rows.add(&event.table, 1);
+        }
+        Err(e) => {
+            savepoint.rollback().await?;
+            outcome.rejected.push((index, error_message(&e)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a value as a field of COPY's text format, escaping the characters
+/// that delimit fields and rows.
+fn write_copy_field(value: &Value, data: &mut Vec<u8>) {
+    let Some(text) = field_text(value) else {
+        data.extend_from_slice(b"\\N");
+        return;
+    };
+
+    for byte in text.bytes() {
+        match byte {
+            b'\\' => data.extend_from_slice(b"\\\\"),
+            b'\t' => data.extend_from_slice(b"\\t"),
+            b'\n' => data.extend_from_slice(b"\\n"),
+            b'\r' => data.extend_from_slice(b"\\r"),
+            byte => data.push(byte),
+        }
+    }
+}
+
+/// Renders a value as the text Postgres parses into a column's type, or
+/// `None` for NULL.
+fn field_text(value: &Value) -> Option<Cow<'_, str>> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(Cow::Borrowed(if *b { "true" } else { "false" })),
+        Value::Number(n) => Some(Cow::Owned(n.to_string())),
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        // Nested values are stored as JSON, for json and jsonb columns
+        Value::Array(_) | Value::Object(_) => Some(Cow::Owned(value.to_string())),
+    }
+}
+
+/// The message of a database error, without the severity and context that
+/// `tokio_postgres::Error` renders around it.
+fn error_message(error: &tokio_postgres::Error) -> String {
+    match error.as_db_error() {
+        Some(db_error) => match db_error.detail() {
+            Some(detail) => format!("{} ({})", db_error.message(), detail),
+            None => db_error.message().to_string(),
+        },
+        None => error.to_string(),
+    }
+}
+
+/// Verifies servers against the Mozilla root certificates when the
+/// connection string asks for TLS.
+fn tls() -> Result<rustls::ClientConfig> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    Ok(rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_root_certificates(roots)
+    .with_no_client_auth())
+}
@@ -0,0 +1,197 @@
+use super::database::{
+    DatabaseEvent, ImportReportSender, Statement, TableRows, quote_identifier, quote_table,
+};
+use crate::model::SqliteImport;
+use anyhow::{Context, Result, anyhow};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, params_from_iter};
+use serde_json::Value;
+use std::io;
+use std::time::Duration;
+
+/// The number of events inserted per transaction.
+const BATCH_SIZE: usize = 1000;
+
+/// Inserts the events of a system into a SQLite database in-process.
+///
+/// Events are inserted in batched transactions with cached prepared
+/// statements. An event that fails, such as for a constraint violation, is
+/// reported on its own without rolling back the rest of its batch.
+pub struct SqliteOutput {
+    system_key: String,
+    connection: Connection,
+    reports: ImportReportSender,
+    /// The number of events in the open transaction.
+    batch: usize,
+    rows: TableRows,
+}
+
+impl SqliteOutput {
+    pub fn open(
+        system_key: String,
+        import: &SqliteImport,
+        reports: ImportReportSender,
+    ) -> Result<Self> {
+        let connection = Connection::open(&import.path).with_context(|| {
+            format!(
+                "Could not open SQLite database {} for system {}",
+                import.path, system_key
+            )
+        })?;
+
+        // Waits out other connections, such as an open sqlite3 shell, rather
+        // than failing with "database is locked"
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.set_prepared_statement_cache_capacity(64);
+
+        Ok(SqliteOutput {
+            system_key,
+            connection,
+            reports,
+            batch: 0,
+            rows: TableRows::default(),
+        })
+    }
+
+    pub fn write_event(&mut self, event: DatabaseEvent) -> Result<()> {
+        if self.batch == 0 {
+            self.connection.execute_batch("BEGIN")?;
+        } else if self.connection.is_autocommit() {
+            return Err(anyhow!(self.rolled_back()));
+        }
+
+        let result = match &event.statement {
+            Statement::Values(value) => self.insert(&event.table, value),
+            Statement::Sql(sql) => self.execute(sql),
+        };
+
+        match result {
+            Ok(()) => self.rows.add(&event.table, 1),
+            Err(message) => event.reject(&self.system_key, message, &self.reports),
+        }
+
+        self.batch += 1;
+        if self.batch >= BATCH_SIZE {
+            self.commit()?;
+        }
+
+        Ok(())
+    }
+
+    fn insert(&self, table: &str, value: &Value) -> Result<(), String> {
+        let columns = DatabaseEvent::columns(value)?;
+
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote_table(table, '"'),
+            columns
+                .keys()
+                .map(|column| quote_identifier(column, '"'))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+
+        let mut statement = self
+            .connection
+            .prepare_cached(&sql)
+            .map_err(|e| e.to_string())?;
+
+        statement
+            .execute(params_from_iter(columns.values().map(sql_value)))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Executes rendered SQL within a savepoint, so that a statement failing
+    /// partway through doesn't leave the event partially applied.
+    fn execute(&self, sql: &str) -> Result<(), String> {
+        self.connection
+            .execute_batch("SAVEPOINT event")
+            .map_err(|e| e.to_string())?;
+
+        match self.connection.execute_batch(sql) {
+            Ok(()) => self
+                .connection
+                .execute_batch("RELEASE event")
+                .map_err(|e| e.to_string()),
+            Err(e) => {
+                let _ = self
+                    .connection
+                    .execute_batch("ROLLBACK TO event; RELEASE event");
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Commits the open transaction, reporting the rows inserted into each
+    /// table. The transaction and its rows are kept if it can't be committed,
+    /// unless SQLite rolled it back, in which case its rows are dropped.
+    fn commit(&mut self) -> Result<()> {
+        if self.batch == 0 {
+            return Ok(());
+        }
+
+        // A statement such as a trigger's RAISE(ROLLBACK) ends the
+        // transaction early, losing the events inserted before it
+        if self.connection.is_autocommit() {
+            return Err(anyhow!(self.rolled_back()));
+        }
+
+        if let Err(e) = self.connection.execute_batch("COMMIT") {
+            if self.connection.is_autocommit() {
+                let message = self.rolled_back();
+                return Err(e).context(message);
+            }
+
+            return Err(e).with_context(|| {
+                format!("Failed to commit to SQLite for system {}", self.system_key)
+            });
+        }
+
+        self.rows.commit(&self.system_key, &self.reports);
+        self.batch = 0;
+        Ok(())
+    }
+
+    /// Drops the rows of a transaction that SQLite rolled back, which are
+    /// never reported as inserted.
+    fn rolled_back(&mut self) -> String {
+        self.rows = TableRows::default();
+
+        format!(
+            "SQLite rolled back {} events for system {}",
+            std::mem::take(&mut self.batch),
+            self.system_key
+        )
+    }
+
+    /// Commits the events inserted so far.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.commit()
+            .map_err(|e| io::Error::other(format!("{:#}", e)))
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        self.commit()?;
+
+        self.connection
+            .close()
+            .map_err(|(_, e)| e)
+            .with_context(|| format!("Failed to close SQLite for system {}", self.system_key))
+    }
+}
+
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        // Nested values are stored as JSON, which SQLite's JSON functions read
+        Value::Array(_) | Value::Object(_) => SqlValue::Text(value.to_string()),
+    }
+}
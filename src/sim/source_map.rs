@@ -0,0 +1,214 @@
+use crate::sim::problem::PathPart;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+/// Maps JSON pointers into a loaded simulation back to the YAML files, lines
+/// and columns they were loaded from.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    locations: HashMap<String, Location>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    content: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+/// A location in a source file, along with the line of YAML it points at.
+#[derive(Debug)]
+pub struct SourceExcerpt {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+impl SourceMap {
+    /// Records the location of every value in a YAML file, whose document is
+    /// loaded into the simulation at `prefix`.
+    ///
+    /// This is best-effort: if the file can't be parsed, nothing is recorded
+    /// and issues within it are not located.
+    pub fn add_file(&mut self, prefix: &[&str], path: &Path, content: &str) {
+        let mut receiver = LocationReceiver {
+            file: self.files.len(),
+            path: prefix.iter().map(|part| part.to_string()).collect(),
+            containers: Vec::new(),
+            locations: HashMap::new(),
+        };
+
+        if Parser::new_from_str(content)
+            .load(&mut receiver, false)
+            .is_err()
+        {
+            return;
+        }
+
+        for (pointer, location) in receiver.locations {
+            self.locations.entry(pointer).or_insert(location);
+        }
+
+        self.files.push(SourceFile {
+            path: path.to_path_buf(),
+            content: content.to_string(),
+        });
+    }
+
+    /// Finds the source of the value at the given path, falling back to the
+    /// closest ancestor for values that don't appear in any file.
+    pub fn locate(&self, path: &[PathPart]) -> Option<SourceExcerpt> {
+        let parts = path
+            .iter()
+            .map(|part| match part {
+                PathPart::Index(i) => i.to_string(),
+                PathPart::Field(s) => s.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        (0..=parts.len()).rev().find_map(|len| {
+            let location = self.locations.get(&pointer(&parts[..len]))?;
+            let file = &self.files[location.file];
+
+            Some(SourceExcerpt {
+                path: file.path.clone(),
+                line: location.line,
+                column: location.column,
+                text: file
+                    .content
+                    .lines()
+                    .nth(location.line.saturating_sub(1))
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+        })
+    }
+}
+
+impl fmt::Display for SourceExcerpt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        writeln!(
+            f,
+            "{gutter}--> {path}:{line}:{column}",
+            path = self.path.to_string_lossy(),
+            line = self.line,
+            column = self.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.text)?;
+        write!(
+            f,
+            "{gutter} | {marker}^",
+            marker = " ".repeat(self.column.saturating_sub(1))
+        )
+    }
+}
+
+/// Builds a JSON pointer, escaping `~` and `/` within each part.
+fn pointer(parts: &[String]) -> String {
+    parts
+        .iter()
+        .map(|part| format!("/{}", part.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+enum Container {
+    Mapping { has_key: bool },
+    Sequence { index: usize },
+}
+
+/// Tracks the path to the current value while receiving parser events.
+struct LocationReceiver {
+    file: usize,
+    path: Vec<String>,
+    containers: Vec<Container>,
+    locations: HashMap<String, Location>,
+}
+
+impl LocationReceiver {
+    fn record(&mut self, mark: Marker) {
+        self.locations
+            .entry(pointer(&self.path))
+            .or_insert(Location {
+                file: self.file,
+                line: mark.line(),
+                // Marker columns are 0-based, despite the documentation
+                column: mark.col() + 1,
+            });
+    }
+
+    /// Moves on from a value that has been fully received.
+    fn end_value(&mut self) {
+        match self.containers.last_mut() {
+            Some(Container::Mapping { has_key }) => {
+                *has_key = false;
+                self.path.pop();
+            }
+            Some(Container::Sequence { .. }) => {
+                self.path.pop();
+            }
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for LocationReceiver {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(..)
+            | Event::Alias(..)
+            | Event::MappingStart(..)
+            | Event::SequenceStart(..) => {}
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.containers.pop();
+                self.end_value();
+                return;
+            }
+            _ => return,
+        }
+
+        // Keys are recorded as the location of their value, since that's
+        // where a value spanning several lines starts
+        if let Some(Container::Mapping { has_key }) = self.containers.last_mut()
+            && !*has_key
+        {
+            *has_key = true;
+
+            let key = match event {
+                Event::Scalar(key, ..) => key,
+                _ => String::new(),
+            };
+
+            self.path.push(key);
+            self.record(mark);
+            return;
+        }
+
+        if let Some(Container::Sequence { index }) = self.containers.last_mut() {
+            self.path.push(index.to_string());
+            *index += 1;
+        }
+
+        self.record(mark);
+
+        match event {
+            Event::MappingStart(..) => self.containers.push(Container::Mapping { has_key: false }),
+            Event::SequenceStart(..) => self.containers.push(Container::Sequence { index: 0 }),
+            _ => self.end_value(),
+        }
+    }
+}
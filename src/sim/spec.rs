@@ -0,0 +1,82 @@
+use crate::model::{Simulation, SimulationRunData};
+use anyhow::{Context, Result};
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::Path;
+
+/// Writes the effects and systems of the run to `spec.yml` in its directory.
+pub fn write_spec(
+    simulation_run_directory: &Path,
+    simulation: &Simulation,
+    simulation_run_data: &SimulationRunData,
+) -> Result<()> {
+    let effects_map: Map<String, Value> = simulation_run_data
+        .effects
+        .iter()
+        .map(|effect| {
+            let key = effect.key.clone();
+            let mut value = serde_json::to_value(effect).unwrap();
+            if let Some(obj) = value.as_object_mut() {
+                obj.remove("key");
+            }
+            (key, value)
+        })
+        .collect();
+
+    let systems_map: Map<String, Value> = simulation_run_data
+        .systems
+        .iter()
+        .map(|system| {
+            let key = system.key.clone();
+            let mut value = serde_json::to_value(system).unwrap();
+            if let Some(obj) = value.as_object_mut() {
+                obj.remove("key");
+            }
+            (key, value)
+        })
+        .collect();
+
+    let mut spec = Map::new();
+    spec.insert("seed".to_string(), json!(simulation.seed));
+    spec.insert("parent".to_string(), json!(simulation.parent));
+    spec.insert("effects".to_string(), json!(effects_map));
+    spec.insert("systems".to_string(), json!(systems_map));
+
+    let spec_path = simulation_run_directory.join("spec.yml");
+    fs::write(spec_path, serde_json::to_string_pretty(&spec)?)?;
+
+    Ok(())
+}
+
+/// Reads the effects and systems of a run back from `spec.yml` in its directory.
+pub fn read_spec(simulation_run_directory: &Path, index: u64) -> Result<SimulationRunData> {
+    let spec_path = simulation_run_directory.join("spec.yml");
+    let content = fs::read_to_string(&spec_path)
+        .with_context(|| format!("Failed to read spec at {}", spec_path.display()))?;
+    let spec: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse spec at {}", spec_path.display()))?;
+
+    Ok(SimulationRunData {
+        index,
+        effects: keyed_values(&spec, "effects")?,
+        systems: keyed_values(&spec, "systems")?,
+    })
+}
+
+/// Deserializes a map of spec entries, restoring the key removed by `write_spec`.
+fn keyed_values<T: serde::de::DeserializeOwned>(spec: &Value, field: &str) -> Result<Vec<T>> {
+    let Some(map) = spec.get(field).and_then(Value::as_object) else {
+        return Ok(Vec::new());
+    };
+
+    map.iter()
+        .map(|(key, value)| {
+            let mut value = value.clone();
+            if let Some(obj) = value.as_object_mut() {
+                obj.insert("key".into(), key.clone().into());
+            }
+            serde_json::from_value(value)
+                .with_context(|| format!("Failed to read {} {} from spec", field, key))
+        })
+        .collect()
+}
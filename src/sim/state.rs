@@ -0,0 +1,136 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const STATE_FILE_NAME: &str = "state.json";
+
+/// Progress of a simulation run, persisted in its run directory so that an
+/// interrupted run can be resumed.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunState {
+    pub simulation: String,
+    pub index: u64,
+    /// When the run was created, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_event_id: Option<u64>,
+    #[serde(default)]
+    pub event_count: u64,
+    #[serde(default)]
+    pub complete: bool,
+    /// The last event written to each output that got ahead of
+    /// `last_event_id` before the run was interrupted, so that resuming
+    /// doesn't write the events re-delivered since then to it again.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub delivered: BTreeMap<String, u64>,
+}
+
+impl RunState {
+    pub fn new(simulation: String, index: u64) -> Self {
+        RunState {
+            simulation,
+            index,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|duration| duration.as_secs()),
+            last_event_id: None,
+            event_count: 0,
+            complete: false,
+            delivered: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(run_directory: &Path) -> Result<Self> {
+        let path = run_directory.join(STATE_FILE_NAME);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read run state at {}", path.display()))?;
+
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse run state at {}", path.display()))
+    }
+
+    /// Writes the state to a temporary file and renames it into place, so a
+    /// crash never leaves a partially written state file behind.
+    pub fn save(&self, run_directory: &Path) -> Result<()> {
+        let path = run_directory.join(STATE_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write run state at {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write run state at {}", path.display()))
+    }
+}
+
+/// Records how far each output of an interrupted run got, keeping the rest of
+/// the state as last saved. The state in memory may be ahead of it, if the
+/// outputs couldn't be flushed in time when the run was interrupted.
+pub fn record_delivered(run_directory: &Path, delivered: BTreeMap<String, u64>) -> Result<()> {
+    let mut run_state = RunState::load(run_directory)?;
+
+    for (output, id) in delivered {
+        let last = run_state.delivered.entry(output).or_default();
+        *last = (*last).max(id);
+    }
+
+    run_state.save(run_directory)
+}
+
+/// Resolves a run index, or `last` for the most recent run, to its directory.
+pub fn run_directory(index: &str) -> Result<PathBuf> {
+    let path = Path::new(".rngo/runs").join(index);
+
+    if !path.is_dir() {
+        bail!("Could not find run directory at {}", path.display())
+    }
+
+    Ok(path)
+}
+
+/// Reads the index of a run from its directory, following the `last` symlink.
+pub fn run_index(run_directory: &Path) -> Result<u64> {
+    fs::canonicalize(run_directory)?
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.parse().ok())
+        .with_context(|| {
+            format!(
+                "Could not determine run index of {}",
+                run_directory.display()
+            )
+        })
+}
+
+/// Points the `last` symlink at a run, warning rather than failing if it
+/// can't be created.
+pub fn link_last_run(index: u64) -> Result<()> {
+    let last_symlink = Path::new(".rngo/runs/last");
+    if last_symlink.symlink_metadata().is_ok() {
+        fs::remove_file(last_symlink)?;
+    }
+    let symlink_result = {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(index.to_string(), last_symlink)
+        }
+        #[cfg(windows)]
+        {
+            std::os::windows::fs::symlink_dir(index.to_string(), last_symlink)
+        }
+    };
+    if let Err(e) = symlink_result {
+        eprintln!(
+            "Warning: could not create symlink at {}: {}",
+            last_symlink.display(),
+            e
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const STATS_FILE_NAME: &str = "run.json";
+
+/// Counters accumulated while the events of a run are written, saved to
+/// `run.json` in the run directory.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunStats {
+    pub events: u64,
+    /// Events received per effect.
+    pub effects: BTreeMap<String, u64>,
+    /// Rows written to each system's import command.
+    pub systems: BTreeMap<String, u64>,
+    /// Rows committed to each table of a built-in database system, keyed by
+    /// system and table.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tables: BTreeMap<String, u64>,
+    /// Error events, grouped by effect and message.
+    pub errors: Vec<ErrorCount>,
+    /// Bytes written to each file in the run directory.
+    pub files: BTreeMap<String, u64>,
+    pub elapsed_seconds: f64,
+    pub events_per_second: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorCount {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effect: Option<String>,
+    pub message: String,
+    pub count: u64,
+}
+
+impl RunStats {
+    pub fn load(run_directory: &Path) -> Result<Self> {
+        let path = run_directory.join(STATS_FILE_NAME);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read run stats at {}", path.display()))?;
+
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse run stats at {}", path.display()))
+    }
+
+    /// Writes the stats to a temporary file and renames it into place, so a
+    /// crash never leaves a partially written stats file behind.
+    pub fn save(&self, run_directory: &Path) -> Result<()> {
+        let path = run_directory.join(STATS_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write run stats at {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write run stats at {}", path.display()))
+    }
+
+    pub fn record_effect(&mut self, effect: &str) {
+        self.events += 1;
+        *self.effects.entry(effect.to_string()).or_default() += 1;
+    }
+
+    pub fn record_system_row(&mut self, system: &str) {
+        *self.systems.entry(system.to_string()).or_default() += 1;
+    }
+
+    pub fn record_table_rows(&mut self, system: &str, table: &str, count: u64) {
+        *self
+            .tables
+            .entry(format!("{}.{}", system, table))
+            .or_default() += count;
+    }
+
+    pub fn record_error(&mut self, effect: Option<&str>, message: &str) {
+        self.events += 1;
+        self.record_import_error(effect, message);
+    }
+
+    /// Counts an event that a system rejected, which was already counted as
+    /// an effect when it was received.
+    pub fn record_import_error(&mut self, effect: Option<&str>, message: &str) {
+        match self
+            .errors
+            .iter_mut()
+            .find(|error| error.effect.as_deref() == effect && error.message == message)
+        {
+            Some(error) => error.count += 1,
+            None => self.errors.push(ErrorCount {
+                effect: effect.map(String::from),
+                message: message.to_string(),
+                count: 1,
+            }),
+        }
+    }
+
+    pub fn error_count(&self) -> u64 {
+        self.errors.iter().map(|error| error.count).sum()
+    }
+
+    /// Fails once a run has reported `error_limit` or more error events,
+    /// pointing to the errors file of the run directory when it has one.
+    pub fn ensure_error_limit_not_reached(
+        &self,
+        error_limit: Option<u64>,
+        run_directory: Option<&Path>,
+    ) -> Result<()> {
+        let error_count = self.error_count();
+
+        if let Some(error_limit) = error_limit
+            && error_count >= error_limit
+        {
+            match run_directory {
+                Some(run_directory) => bail!(
+                    "Run failed with {} error events, see {}",
+                    error_count,
+                    run_directory
+                        .join(crate::sim::sink::ERRORS_FILE_NAME)
+                        .display()
+                ),
+                None => bail!("Run failed with {} error events", error_count),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the wall time of a session of the run, from which throughput is
+    /// derived.
+    pub fn add_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed_seconds += elapsed.as_secs_f64();
+        self.events_per_second = if self.elapsed_seconds > 0.0 {
+            self.events as f64 / self.elapsed_seconds
+        } else {
+            0.0
+        };
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "  events: {} in {:.1}s ({:.0} events/s)",
+            self.events, self.elapsed_seconds, self.events_per_second
+        )?;
+
+        if !self.effects.is_empty() {
+            write!(f, "\n  effects:")?;
+            for (effect, count) in &self.effects {
+                write!(f, "\n    {:<32} {:>9}", effect, count)?;
+            }
+        }
+
+        if !self.systems.is_empty() {
+            write!(f, "\n  systems:")?;
+            for (system, count) in &self.systems {
+                write!(f, "\n    {:<32} {:>9} rows", system, count)?;
+            }
+        }
+
+        if !self.tables.is_empty() {
+            write!(f, "\n  tables:")?;
+            for (table, count) in &self.tables {
+                write!(f, "\n    {:<32} {:>9} rows", table, count)?;
+            }
+        }
+
+        if !self.errors.is_empty() {
+            write!(f, "\n  errors:")?;
+            for error in &self.errors {
+                write!(
+                    f,
+                    "\n    {:<32} {:>9}  {}",
+                    error.effect.as_deref().unwrap_or("-"),
+                    error.count,
+                    error.message
+                )?;
+            }
+        }
+
+        if !self.files.is_empty() {
+            write!(f, "\n  files:")?;
+            for (file, size) in &self.files {
+                write!(f, "\n    {:<32} {:>9}", file, format_size(*size))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,419 @@
+use crate::model::EventData;
+use crate::sim::api::{self, ApiClient};
+use crate::sim::ndjson::LineFramer;
+use crate::sim::problem::Problem;
+use crate::sim::progress::Progress;
+use crate::sim::retry;
+use crate::sim::sink::{self, SimulationSink, SinkReport};
+use crate::sim::state::{self, RunState};
+use anyhow::{Context, Result, bail};
+use futures::StreamExt;
+use reqwest::StatusCode;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// How often the sink is flushed and the run state persisted while streaming.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long import commands are given to exit once a run is interrupted.
+const INTERRUPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why streaming a run stopped.
+#[derive(Debug, PartialEq)]
+enum StreamEnd {
+    /// The API reported that every event has been sent.
+    Complete,
+    /// The sink's error limit was reached.
+    ErrorLimitReached,
+    /// The process received Ctrl-C or SIGTERM.
+    Interrupted,
+}
+
+/// Streams the events of a simulation run into the sink, starting after
+/// `run_state.last_event_id` and reconnecting until the API reports that the
+/// run is complete, the sink's error limit is reached or the process is
+/// interrupted.
+///
+/// When a run directory is given, the sink is periodically flushed and the run
+/// state saved there along with its stats, so that an interrupted run can be
+/// resumed. Since the
+/// sink is flushed before the state is saved, a resumed run may re-deliver the
+/// events received since the last checkpoint, but never skips any. When the
+/// run is interrupted rather than killed, how far each output got is recorded
+/// with `state::record_delivered`, so that those events aren't written to it
+/// twice.
+///
+/// Events are only handed to the sink as complete lines, so stopping between
+/// them leaves every output with whole records.
+async fn stream_run(
+    api: &ApiClient,
+    run_state: &mut RunState,
+    simulation_sink: &mut SimulationSink,
+    progress: &mut Progress,
+    run_directory: Option<&Path>,
+) -> Result<StreamEnd> {
+    let stream_path = format!(
+        "/simulations/{simulation_key}/runs/{run_index}/stream",
+        simulation_key = run_state.simulation,
+        run_index = run_state.index
+    );
+
+    if let Some(run_directory) = run_directory {
+        run_state.save(run_directory)?;
+    }
+
+    let started_at = Instant::now();
+    let mut last_checkpoint = started_at;
+
+    let mut stream_end = StreamEnd::Complete;
+
+    // The reason the last connection failed and how long to wait before
+    // reconnecting, counting failures since events were last received
+    let mut retry: Option<(String, Duration)> = None;
+    let mut failures = 0;
+
+    let interrupt = interrupted();
+    tokio::pin!(interrupt);
+
+    // Loop to handle reconnection
+    'stream: loop {
+        if let Some((reason, delay)) = retry.take() {
+            failures += 1;
+
+            if failures >= api.retry.max_attempts {
+                progress.finish();
+                bail!("Stream failed after {} attempts: {}", failures, reason);
+            }
+
+            progress.println(format!(
+                "Stream error: {}, reconnecting in {} (attempt {} of {})",
+                reason,
+                retry::format_delay(delay),
+                failures + 1,
+                api.retry.max_attempts
+            ));
+            progress.record_reconnect();
+
+            tokio::select! {
+                _ = time::sleep(delay) => {}
+                _ = &mut interrupt => {
+                    stream_end = StreamEnd::Interrupted;
+                    break 'stream;
+                }
+            }
+        }
+
+        let mut request = api
+            .stream_request(&stream_path)
+            .header("Accept", "application/x-ndjson");
+
+        // Add lastEventId query parameter if we have one
+        if let Some(event_id) = run_state.last_event_id {
+            request = request.query(&[("lastEventId", event_id.to_string())]);
+        }
+
+        let response = tokio::select! {
+            response = request.send() => response,
+            _ = &mut interrupt => {
+                stream_end = StreamEnd::Interrupted;
+                break 'stream;
+            }
+        };
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => match api.retry.error_backoff(&e, failures + 1, true) {
+                Some(delay) if failures + 1 < api.retry.max_attempts => {
+                    retry = Some((e.to_string(), delay));
+                    continue;
+                }
+                _ => {
+                    progress.finish();
+                    return Err(e).with_context(|| "Failed to connect to the stream")?;
+                }
+            },
+        };
+
+        let status = response.status();
+
+        // If we get 204 No Content, the simulation is complete
+        if status == StatusCode::NO_CONTENT {
+            break;
+        }
+
+        if !status.is_success() {
+            if let Some(delay) = api.retry.response_backoff(&response, failures + 1, true)
+                && failures + 1 < api.retry.max_attempts
+            {
+                retry = Some((status.to_string(), delay));
+                continue;
+            }
+
+            progress.finish();
+            let problem = Problem::from_response(response).await;
+            return Err(problem).with_context(|| "API error while streaming")?;
+        }
+
+        // Process the NDJSON stream
+        let mut byte_stream = response.bytes_stream();
+        let mut framer = LineFramer::default();
+        let mut received = false;
+
+        loop {
+            let chunk_result = tokio::select! {
+                chunk_result = byte_stream.next() => chunk_result,
+                _ = &mut interrupt => {
+                    stream_end = StreamEnd::Interrupted;
+                    break 'stream;
+                }
+            };
+
+            let Some(chunk_result) = chunk_result else {
+                break;
+            };
+
+            let chunk = match chunk_result {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    retry = Some((e.to_string(), api.retry.backoff(failures + 1)));
+                    break; // Break inner loop to reconnect
+                }
+            };
+
+            framer.push(&chunk);
+
+            // Process complete lines
+            while let Some(line) = framer.next_line() {
+                if !line.trim_ascii().is_empty() {
+                    match serde_json::from_slice::<EventData>(&line) {
+                        Ok(event_data) => {
+                            let event_id = match &event_data {
+                                EventData::Effect { id, .. } => *id,
+                                EventData::Error { id, .. } => *id,
+                            };
+
+                            // Events the run already received aren't written
+                            // or counted again if the stream repeats them
+                            if run_state
+                                .last_event_id
+                                .is_some_and(|last_event_id| event_id <= last_event_id)
+                            {
+                                continue;
+                            }
+
+                            progress.record(&event_data);
+
+                            // The sink reports errors on stderr
+                            if let EventData::Error { .. } = event_data {
+                                progress.clear();
+                            }
+
+                            // Writing waits while the event's output is
+                            // backed up, which an interrupt cuts short,
+                            // leaving the event to be delivered on resume
+                            tokio::select! {
+                                _ = simulation_sink.write_event(event_data) => {}
+                                _ = &mut interrupt => {
+                                    stream_end = StreamEnd::Interrupted;
+                                    break 'stream;
+                                }
+                            }
+
+                            // Track the last event ID for reconnection
+                            run_state.last_event_id = Some(event_id);
+                            run_state.event_count += 1;
+                            received = true;
+
+                            if simulation_sink.error_limit_reached() {
+                                stream_end = StreamEnd::ErrorLimitReached;
+                                break 'stream;
+                            }
+                        }
+                        Err(e) => progress.println(format!(
+                            "Failed to parse NDJSON line: {} - Error: {}",
+                            String::from_utf8_lossy(&line),
+                            e
+                        )),
+                    }
+                }
+            }
+
+            if let Some(run_directory) = run_directory
+                && last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL
+            {
+                tokio::select! {
+                    result = simulation_sink.flush() => result?,
+                    _ = &mut interrupt => {
+                        stream_end = StreamEnd::Interrupted;
+                        break 'stream;
+                    }
+                }
+
+                save_checkpoint(run_directory, run_state, simulation_sink, started_at)?;
+                last_checkpoint = Instant::now();
+            }
+
+            progress.tick();
+        }
+
+        // If we reach here, the connection ended without 204, so reconnect,
+        // immediately if it made progress and otherwise after a backoff
+        if received {
+            failures = 0;
+        } else if retry.is_none() {
+            retry = Some((
+                "connection closed without events".to_string(),
+                api.retry.backoff(failures + 1),
+            ));
+        }
+    }
+
+    progress.finish();
+
+    if stream_end == StreamEnd::Interrupted {
+        progress.println("Interrupted, finishing the run's outputs...");
+    }
+
+    if let Some(run_directory) = run_directory {
+        if stream_end == StreamEnd::Interrupted {
+            // If an output can't catch up in time, the state is left at the
+            // last checkpoint, so the events queued for it are resumed
+            if let Ok(result) = time::timeout(INTERRUPT_TIMEOUT, simulation_sink.flush()).await {
+                result?;
+                save_checkpoint(run_directory, run_state, simulation_sink, started_at)?;
+            }
+        } else {
+            simulation_sink.flush().await?;
+            run_state.complete = stream_end == StreamEnd::Complete;
+            save_checkpoint(run_directory, run_state, simulation_sink, started_at)?;
+        }
+    }
+
+    // Past this point, such as while waiting for import commands to exit, a
+    // further interrupt exits immediately
+    tokio::spawn(async {
+        interrupted().await;
+        std::process::exit(130);
+    });
+
+    Ok(stream_end)
+}
+
+/// Streams a run with `stream_run` and finishes its sink, saving its stats
+/// and failing the same way for every command that streams a run.
+///
+/// An interrupted run is reported as an error, which tells how to resume it
+/// when it has a run directory, and `cancel_on_interrupt` cancels it in the
+/// API as well, rather than leaving it to be streamed again.
+pub async fn finish_run(
+    api: &ApiClient,
+    run_state: &mut RunState,
+    mut simulation_sink: SimulationSink,
+    progress: &mut Progress,
+    run_directory: Option<&Path>,
+    cancel_on_interrupt: bool,
+) -> Result<SinkReport> {
+    let started_at = Instant::now();
+
+    let stream_end = match stream_run(
+        api,
+        run_state,
+        &mut simulation_sink,
+        progress,
+        run_directory,
+    )
+    .await
+    {
+        Ok(stream_end) => stream_end,
+        Err(e) => {
+            // The sink is still finished, so the events recorded so far end
+            // in a complete frame and its imports aren't cut off
+            if let Err(finish_error) = simulation_sink.finish_within(INTERRUPT_TIMEOUT).await {
+                eprintln!(
+                    "Warning: could not finish the run's outputs: {:#}",
+                    finish_error
+                );
+            }
+
+            return Err(e);
+        }
+    };
+
+    let mut report = if stream_end == StreamEnd::Interrupted {
+        simulation_sink.finish_within(INTERRUPT_TIMEOUT).await?
+    } else {
+        simulation_sink.finish().await?
+    };
+    report.stats.add_elapsed(started_at.elapsed());
+
+    if stream_end != StreamEnd::Interrupted {
+        if let Some(run_directory) = run_directory {
+            report.stats.save(run_directory)?;
+        }
+
+        sink::ensure_imports_succeeded(&report.import_statuses)?;
+        return Ok(report);
+    }
+
+    if cancel_on_interrupt
+        && let Err(e) =
+            api::cancel_simulation_run(api, &run_state.simulation, run_state.index).await
+    {
+        eprintln!("Warning: could not cancel run {}: {:#}", run_state.index, e);
+    }
+
+    // The stats saved with the last checkpoint are kept, since they match the
+    // events the run resumes after
+    match run_directory {
+        Some(run_directory) => {
+            state::record_delivered(run_directory, report.delivered)?;
+
+            bail!(
+                "Interrupted run {index}, resume it with `rngo sim resume {index}`",
+                index = run_state.index
+            )
+        }
+        None => bail!("Interrupted run {}", run_state.index),
+    }
+}
+
+/// Saves the run state along with the stats of the events it accounts for,
+/// so that resuming carries on from the same point for both.
+fn save_checkpoint(
+    run_directory: &Path,
+    run_state: &RunState,
+    simulation_sink: &SimulationSink,
+    started_at: Instant,
+) -> Result<()> {
+    let mut stats = simulation_sink.stats().clone();
+    stats.add_elapsed(started_at.elapsed());
+    stats.save(run_directory)?;
+
+    run_state.save(run_directory)
+}
+
+/// Resolves when the process receives Ctrl-C or, on Unix, SIGTERM.
+async fn interrupted() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => {
+                    if result.is_err() {
+                        terminate.recv().await;
+                    }
+                }
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+
+    // Without a signal handler the process is never considered interrupted
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
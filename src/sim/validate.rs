@@ -0,0 +1,231 @@
+use crate::model::FormatType;
+use crate::sim::load;
+use crate::sim::problem::{PathPart, Problem, ProblemIssue};
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+/// The built-in database systems, one of which can be set in place of an
+/// import command.
+const DATABASE_KINDS: [&str; 3] = ["sqlite", "postgres", "mysql"];
+
+/// Checks the simulation definitions locally, without calling the API.
+pub async fn validate(file: Option<String>) -> Result<()> {
+    let config = crate::config::get_config()?;
+
+    let (sim, source_map) = if let Some(file) = file {
+        load::load_sim_from_file(file)?
+    } else {
+        load::load_sim_from_project_directory(&config)?
+    };
+
+    let issues = validate_sim(&sim);
+
+    if issues.is_empty() {
+        println!("Simulation is valid");
+        return Ok(());
+    }
+
+    Err(Problem::new("Invalid simulation".into(), issues).locate(&source_map))
+        .with_context(|| "Validation error")
+}
+
+/// Returns an issue for every problem that can be detected without the API.
+fn validate_sim(sim: &Value) -> Vec<ProblemIssue> {
+    let mut issues = Vec::new();
+
+    let Some(sim) = sim.as_object() else {
+        issues.push(ProblemIssue::new(vec![], "should be an object".into()));
+        return issues;
+    };
+
+    let empty = Map::new();
+
+    let systems = match sim.get("systems") {
+        None => &empty,
+        Some(Value::Object(systems)) => systems,
+        Some(_) => {
+            issues.push(issue(&["systems"], "should be an object"));
+            &empty
+        }
+    };
+
+    let effects = match sim.get("effects") {
+        Some(Value::Object(effects)) => effects,
+        Some(_) => {
+            issues.push(issue(&["effects"], "should be an object"));
+            &empty
+        }
+        None => {
+            issues.push(issue(&["effects"], "is required"));
+            &empty
+        }
+    };
+
+    for (key, system) in systems {
+        validate_system(key, system, &mut issues);
+    }
+
+    for (key, effect) in effects {
+        validate_effect(key, effect, effects, systems, &mut issues);
+    }
+
+    issues
+}
+
+fn validate_system(key: &str, system: &Value, issues: &mut Vec<ProblemIssue>) {
+    let Some(system) = system.as_object() else {
+        issues.push(issue(&["systems", key], "should be an object"));
+        return;
+    };
+
+    match system.get("format").and_then(|format| format.get("type")) {
+        None => issues.push(issue(&["systems", key, "format", "type"], "is required")),
+        Some(format_type) => match serde_json::from_value::<FormatType>(format_type.clone()) {
+            Ok(FormatType::Csv | FormatType::Parquet) => issues.push(issue(
+                &["systems", key, "format", "type"],
+                &format!(
+                    "{} is only supported for effects written to files",
+                    format_type
+                ),
+            )),
+            Ok(_) => {}
+            Err(_) => issues.push(issue(
+                &["systems", key, "format", "type"],
+                &format!("unknown format type {}", format_type),
+            )),
+        },
+    }
+
+    let import = system.get("import");
+    let databases: Vec<(&str, &Value)> = DATABASE_KINDS
+        .iter()
+        .filter_map(|kind| Some((*kind, import?.get(*kind)?)))
+        .collect();
+
+    if databases.len() > 1 {
+        issues.push(issue(
+            &["systems", key, "import"],
+            "should set only one of sqlite, postgres and mysql",
+        ));
+    }
+
+    for (kind, database) in &databases {
+        if *kind == "sqlite" && database.get("path").is_none() {
+            issues.push(issue(
+                &["systems", key, "import", kind, "path"],
+                "is required",
+            ));
+        }
+
+        for (field, value) in database.as_object().into_iter().flatten() {
+            let message = match field.as_str() {
+                "path" | "url" | "urlEnv" if !value.is_string() => "should be a string",
+                "disableForeignKeyChecks" if !value.is_boolean() => "should be a boolean",
+                _ => continue,
+            };
+
+            issues.push(issue(&["systems", key, "import", kind, field], message));
+        }
+    }
+
+    if databases.is_empty() {
+        match import.and_then(|import| import.get("command")) {
+            None => issues.push(issue(
+                &["systems", key, "import", "command"],
+                "is required unless import.sqlite, import.postgres or import.mysql is set",
+            )),
+            Some(Value::String(_)) => {}
+            Some(_) => issues.push(issue(
+                &["systems", key, "import", "command"],
+                "should be a string",
+            )),
+        }
+    }
+}
+
+fn validate_effect(
+    key: &str,
+    effect: &Value,
+    effects: &Map<String, Value>,
+    systems: &Map<String, Value>,
+    issues: &mut Vec<ProblemIssue>,
+) {
+    let Some(effect) = effect.as_object() else {
+        issues.push(issue(&["effects", key], "should be an object"));
+        return;
+    };
+
+    match effect.get("system") {
+        None => {}
+        Some(Value::String(system)) if systems.contains_key(system) => {}
+        Some(Value::String(system)) => issues.push(issue(
+            &["effects", key, "system"],
+            &format!("system '{}' does not exist", system),
+        )),
+        Some(_) => issues.push(issue(&["effects", key, "system"], "should be a string")),
+    }
+
+    if let Some(schema) = effect.get("schema") {
+        let mut path = vec![
+            PathPart::Field("effects".into()),
+            PathPart::Field(key.into()),
+            PathPart::Field("schema".into()),
+        ];
+        validate_references(schema, effects, &mut path, issues);
+    }
+}
+
+/// Walks a schema and checks that every `reference` type points at an effect.
+fn validate_references(
+    value: &Value,
+    effects: &Map<String, Value>,
+    path: &mut Vec<PathPart>,
+    issues: &mut Vec<ProblemIssue>,
+) {
+    match value {
+        Value::Object(map) => {
+            if map.get("type").and_then(Value::as_str) == Some("reference") {
+                match map.get("effect") {
+                    Some(Value::String(effect)) if effects.contains_key(effect) => {}
+                    Some(Value::String(effect)) => issues.push(ProblemIssue::new(
+                        with_field(path, "effect"),
+                        format!("effect '{}' does not exist", effect),
+                    )),
+                    _ => issues.push(ProblemIssue::new(
+                        with_field(path, "effect"),
+                        "is required for reference types".into(),
+                    )),
+                }
+            }
+
+            for (key, value) in map {
+                path.push(PathPart::Field(key.clone()));
+                validate_references(value, effects, path, issues);
+                path.pop();
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                path.push(PathPart::Index(i as i64));
+                validate_references(value, effects, path, issues);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn with_field(path: &[PathPart], field: &str) -> Vec<PathPart> {
+    let mut path = path.to_vec();
+    path.push(PathPart::Field(field.into()));
+    path
+}
+
+fn issue(path: &[&str], message: &str) -> ProblemIssue {
+    ProblemIssue::new(
+        path.iter()
+            .map(|part| PathPart::Field(part.to_string()))
+            .collect(),
+        message.into(),
+    )
+}
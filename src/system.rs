@@ -9,7 +9,7 @@ pub async fn infer(
     verbose: bool,
 ) -> Result<()> {
     let config = crate::config::get_config()?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client(&config)?;
 
     let response = client
         .get(format!(